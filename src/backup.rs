@@ -0,0 +1,339 @@
+//! # Backup History
+//!
+//! Every PATH write is preceded by a timestamped `.reg` backup in
+//! `%LOCALAPPDATA%\wanderlust\backups`. Each file can be imported with `regedit` by hand,
+//! or restored with `wanderlust restore <id>`.
+//!
+//! ## Retention
+//! The newest `MAX_BACKUPS_PER_SCOPE` backups of each scope are kept. The *oldest* backup of
+//! each scope is never pruned: it is the PATH as it was before Wanderlust first touched it.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use crate::cleaner;
+use crate::plan::Scope;
+use crate::system::{PathValue, SystemOps, ValueKind};
+
+/// How many backups of each scope are kept (in addition to the original one).
+pub const MAX_BACKUPS_PER_SCOPE: usize = 20;
+
+const USER_KEY: &str = r"HKEY_CURRENT_USER\Environment";
+const SYSTEM_KEY: &str = r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Control\Session Manager\Environment";
+
+/// A stored PATH value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// File stem, e.g. `20261016-230000-user` (UTC), with a `-N` suffix on collisions.
+    pub id: String,
+    pub scope: Scope,
+    /// The PATH value exactly as it was in the Registry (unexpanded).
+    pub value: String,
+    /// `REG_SZ` or `REG_EXPAND_SZ`.
+    pub kind: ValueKind,
+}
+
+/// The directory holding the backup history.
+pub fn backup_dir(system: &impl SystemOps) -> Option<PathBuf> {
+    system.app_data_dir().map(|dir| dir.join("backups"))
+}
+
+/// Writes a new backup of `value` for `scope` and prunes old ones.
+pub fn create_backup(system: &impl SystemOps, scope: Scope, value: &PathValue) -> Result<Backup> {
+    let dir = backup_dir(system).context("Could not determine the backup directory")?;
+    let existing: Vec<PathBuf> = system.list_data_files(&dir);
+
+    // Timestamps are only second-resolution; add a counter on collision.
+    // Count past the highest existing counter, since pruning may have freed lower ones.
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let base = format!("{}-{}", timestamp_id(secs), scope_label(scope));
+    let last = existing.iter()
+        .filter_map(|p| p.file_stem())
+        .map(|s| s.to_string_lossy().to_string())
+        .filter(|stem| stem == &base || stem.starts_with(&format!("{}-", base)))
+        .map(|stem| id_sort_key(&stem).1)
+        .max();
+    let id = match last {
+        Some(n) => format!("{}-{}", base, n + 1),
+        None => base,
+    };
+
+    let path = dir.join(format!("{}.reg", id));
+    system.write_data_file(&path, &to_reg(scope, value))?;
+    info!("Backed up old {:?} PATH to {:?}", scope, path);
+
+    prune_backups(system, scope);
+
+    Ok(Backup { id, scope, value: value.data.clone(), kind: value.kind })
+}
+
+/// All readable backups, oldest first.
+pub fn list_backups(system: &impl SystemOps) -> Vec<Backup> {
+    let Some(dir) = backup_dir(system) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<Backup> = system.list_data_files(&dir)
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("reg")))
+        .filter_map(|p| read_backup(system, p))
+        .collect();
+    backups.sort_by_key(|b| id_sort_key(&b.id));
+    backups
+}
+
+/// Looks up a single backup by id.
+pub fn find_backup(system: &impl SystemOps, id: &str) -> Result<Backup> {
+    match list_backups(system).into_iter().find(|b| b.id == id) {
+        Some(backup) => Ok(backup),
+        None => bail!("No backup with id '{}'. Run 'wanderlust backups list' to see available backups.", id),
+    }
+}
+
+/// Writes a stored PATH back to the Registry and broadcasts the change.
+///
+/// Goes through `cleaner::apply_path_value` with the stored registry type, so the current value
+/// is backed up first (a restore can itself be undone) and a broken result is rolled back.
+pub fn restore_backup(system: &impl SystemOps, id: &str) -> Result<()> {
+    let backup = find_backup(system, id)?;
+    let value = PathValue { data: backup.value.clone(), kind: backup.kind };
+    cleaner::apply_path_value(system, backup.scope, &value)?;
+    info!("Restored {:?} PATH from backup {}", backup.scope, backup.id);
+    Ok(())
+}
+
+/// Prints the backup history as a table.
+pub fn print_backup_list(system: &impl SystemOps) {
+    let backups = list_backups(system);
+    if backups.is_empty() {
+        println!("No backups yet. One is written before every PATH change.");
+        return;
+    }
+    println!("{:<28} {:<7} FOLDERS", "ID", "SCOPE");
+    for b in &backups {
+        let count = b.value.split(';').filter(|s| !s.is_empty()).count();
+        println!("{:<28} {:<7} {}", b.id, scope_label(b.scope), count);
+    }
+}
+
+/// Prints one backup, one PATH entry per line.
+pub fn print_backup(system: &impl SystemOps, id: &str) -> Result<()> {
+    let backup = find_backup(system, id)?;
+    println!("Backup {} ({} PATH)", backup.id, scope_label(backup.scope));
+    for entry in backup.value.split(';').filter(|s| !s.is_empty()) {
+        println!("  {}", entry);
+    }
+    Ok(())
+}
+
+/// Deletes all but the newest `MAX_BACKUPS_PER_SCOPE` backups of `scope`, always keeping the oldest.
+fn prune_backups(system: &impl SystemOps, scope: Scope) {
+    let Some(dir) = backup_dir(system) else {
+        return;
+    };
+    let suffix = format!("-{}", scope_label(scope));
+    let mut files: Vec<PathBuf> = system.list_data_files(&dir)
+        .into_iter()
+        .filter(|p| p.file_stem().is_some_and(|s| {
+            let stem = s.to_string_lossy();
+            stem.ends_with(&suffix) || stem.contains(&format!("{}-", suffix))
+        }))
+        .collect();
+    files.sort_by_key(|p| id_sort_key(&p.file_stem().unwrap_or_default().to_string_lossy()));
+
+    if files.len() <= MAX_BACKUPS_PER_SCOPE + 1 {
+        return;
+    }
+    let excess = files.len() - MAX_BACKUPS_PER_SCOPE - 1;
+    // files[0] is the original PATH; prune right after it.
+    for path in &files[1..=excess] {
+        if let Err(e) = system.remove_data_file(path) {
+            warn!("Failed to prune old backup {:?}: {}", path, e);
+        }
+    }
+}
+
+/// Chronological sort key for an id: `(timestamp, collision counter)`.
+///
+/// Plain string order would put `...-user-10` before `...-user-2`.
+fn id_sort_key(id: &str) -> (String, u32) {
+    let mut parts = id.splitn(4, '-');
+    let timestamp = format!("{}-{}", parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let counter = parts.nth(1).and_then(|n| n.parse().ok()).unwrap_or(1);
+    (timestamp, counter)
+}
+
+fn read_backup(system: &impl SystemOps, path: &Path) -> Option<Backup> {
+    let content = system.read_data_file(path).ok()?;
+    let (scope, value) = parse_reg(&content)?;
+    let id = path.file_stem()?.to_string_lossy().to_string();
+    Some(Backup { id, scope, value: value.data, kind: value.kind })
+}
+
+fn scope_label(scope: Scope) -> &'static str {
+    match scope {
+        Scope::User => "user",
+        Scope::System => "system",
+    }
+}
+
+/// Renders a `.reg` file that `regedit` can import to restore `value`.
+///
+/// `REG_SZ` is written as a quoted string. `REG_EXPAND_SZ` has no quoted form in `.reg` files,
+/// so it is written as `hex(2):` UTF-16LE bytes, just like `regedit` exports it.
+fn to_reg(scope: Scope, value: &PathValue) -> String {
+    let key = match scope {
+        Scope::User => USER_KEY,
+        Scope::System => SYSTEM_KEY,
+    };
+    let data = match value.kind {
+        ValueKind::String => {
+            // Escape backslashes and quotes for .reg file format ("\" -> "\\")
+            format!("\"{}\"", value.data.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        ValueKind::ExpandString => {
+            let bytes: Vec<String> = value.data.encode_utf16()
+                .chain(std::iter::once(0))
+                .flat_map(|unit| unit.to_le_bytes())
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            format!("hex(2):{}", bytes.join(","))
+        }
+    };
+    format!("Windows Registry Editor Version 5.00\n\n[{}]\n\"Path\"={}\n", key, data)
+}
+
+/// Parses a `.reg` file written by `to_reg`.
+fn parse_reg(content: &str) -> Option<(Scope, PathValue)> {
+    let mut scope = None;
+    let mut value = None;
+    for line in content.lines().map(str::trim) {
+        if let Some(key) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            scope = if key.eq_ignore_ascii_case(USER_KEY) {
+                Some(Scope::User)
+            } else if key.eq_ignore_ascii_case(SYSTEM_KEY) {
+                Some(Scope::System)
+            } else {
+                None
+            };
+        } else if let Some(raw) = line.strip_prefix("\"Path\"=\"").and_then(|l| l.strip_suffix('"')) {
+            let mut unescaped = String::with_capacity(raw.len());
+            let mut chars = raw.chars();
+            while let Some(c) = chars.next() {
+                if c == '\\' {
+                    unescaped.extend(chars.next());
+                } else {
+                    unescaped.push(c);
+                }
+            }
+            value = Some(PathValue { data: unescaped, kind: ValueKind::String });
+        } else if let Some(hex) = line.strip_prefix("\"Path\"=hex(2):") {
+            let bytes: Vec<u8> = hex.split(',')
+                .map(|b| u8::from_str_radix(b.trim(), 16))
+                .collect::<Result<_, _>>()
+                .ok()?;
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+            let data = String::from_utf16(&units).ok()?.trim_end_matches('\0').to_string();
+            value = Some(PathValue { data, kind: ValueKind::ExpandString });
+        }
+    }
+    Some((scope?, value?))
+}
+
+/// Formats seconds since the Unix epoch as a sortable UTC `YYYYMMDD-HHMMSS` string.
+fn timestamp_id(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days (proleptic Gregorian), see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    fn sz(data: &str) -> PathValue {
+        PathValue { data: data.to_string(), kind: ValueKind::String }
+    }
+
+    #[test]
+    fn test_timestamp_id() {
+        assert_eq!(timestamp_id(0), "19700101-000000");
+        assert_eq!(timestamp_id(951_782_400), "20000229-000000");
+        assert_eq!(timestamp_id(1_760_655_600), "20251016-230000");
+    }
+
+    #[test]
+    fn test_reg_round_trip() {
+        let value = sz(r#"C:\Program Files\Git\cmd;C:\Weird "quoted"\bin"#);
+        assert_eq!(parse_reg(&to_reg(Scope::System, &value)), Some((Scope::System, value)));
+        assert_eq!(parse_reg(&to_reg(Scope::User, &sz(""))), Some((Scope::User, sz(""))));
+    }
+
+    #[test]
+    fn test_reg_round_trip_expand_sz() {
+        let value = PathValue { data: r"%USERPROFILE%\.cargo\bin;C:\Tools".to_string(), kind: ValueKind::ExpandString };
+        let reg = to_reg(Scope::User, &value);
+
+        assert!(reg.contains("\"Path\"=hex(2):25,00,55,00"), "REG_EXPAND_SZ must be exported as hex(2): {}", reg);
+        assert!(reg.trim_end().ends_with("00,00"), "Value must be null-terminated: {}", reg);
+        assert_eq!(parse_reg(&reg), Some((Scope::User, value)));
+    }
+
+    #[test]
+    fn test_history_keeps_original_and_prunes() {
+        let system = MockSystem::new();
+        for i in 0..MAX_BACKUPS_PER_SCOPE + 5 {
+            create_backup(&system, Scope::User, &sz(&format!(r"C:\v{}", i))).unwrap();
+        }
+        create_backup(&system, Scope::System, &sz(r"C:\Windows")).unwrap();
+
+        let backups = list_backups(&system);
+        let user: Vec<&str> = backups.iter().filter(|b| b.scope == Scope::User).map(|b| b.value.as_str()).collect();
+
+        assert_eq!(user.len(), MAX_BACKUPS_PER_SCOPE + 1);
+        assert_eq!(user[0], r"C:\v0", "The original PATH is never pruned");
+        assert_eq!(user[1], r"C:\v5");
+        assert_eq!(backups.iter().filter(|b| b.scope == Scope::System).count(), 1);
+    }
+
+    #[test]
+    fn test_restore_writes_value_and_broadcasts() {
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Hand\Curated").unwrap();
+        let original = create_backup(&system, Scope::User, &sz(r"C:\Hand\Curated")).unwrap();
+        system.write_user_path_registry(r"C:\Healed").unwrap();
+
+        restore_backup(&system, &original.id).unwrap();
+
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\Hand\Curated");
+        assert!(*system.broadcast_called.lock().unwrap());
+        assert!(list_backups(&system).iter().any(|b| b.value == r"C:\Healed"), "Restore backs up what it overwrites");
+        assert!(restore_backup(&system, "nope").is_err());
+    }
+
+    #[test]
+    fn test_restore_keeps_expand_sz() {
+        let system = MockSystem::new();
+        let original = PathValue { data: r"C:\Tools".to_string(), kind: ValueKind::ExpandString };
+        system.write_path_value(Scope::User, &original).unwrap();
+        let backup = create_backup(&system, Scope::User, &original).unwrap();
+        system.write_path_value(Scope::User, &sz(r"C:\Healed")).unwrap();
+
+        restore_backup(&system, &backup.id).unwrap();
+
+        assert_eq!(system.read_path_value(Scope::User).unwrap(), original, "Restored as REG_EXPAND_SZ, not REG_SZ");
+    }
+}
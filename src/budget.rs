@@ -0,0 +1,167 @@
+//! # PATH Length Budget
+//!
+//! Windows has two length limits that matter for PATH:
+//!
+//! * **2047 characters**: `setx`, the old System Properties dialog and many installers
+//!   silently truncate longer values, usually while appending to them.
+//! * **32767 characters**: the maximum length of any environment variable. A new process
+//!   never sees anything past it.
+//!
+//! `measure` reports both the stored (unexpanded) and the effective (expanded) lengths,
+//! `variable_form` provides the shorter `%VAR%`-relative spelling used by compaction.
+
+use serde::{Deserialize, Serialize};
+use crate::system::SystemOps;
+
+/// Older tools (`setx`, some installers) silently truncate PATH values longer than this.
+pub const LEGACY_PATH_LIMIT: usize = 2047;
+/// The maximum length of any environment variable; Windows ignores everything beyond it.
+pub const MAX_PATH_LENGTH: usize = 32767;
+
+/// Within this many percent of a limit counts as approaching it.
+const HEADROOM_PERCENT: usize = 10;
+
+/// Variables that are defined for every user at logon, so they are safe in a `REG_EXPAND_SZ` PATH.
+const COMPACTION_VARIABLES: &[&str] = &[
+    "LOCALAPPDATA",
+    "APPDATA",
+    "USERPROFILE",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "ProgramData",
+    "SystemRoot",
+];
+
+/// PATH lengths, in characters, against the limits Windows and legacy tools impose.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LengthBudget {
+    /// The System PATH as stored, `%VAR%` references unexpanded.
+    pub system: usize,
+    /// The User PATH as stored, `%VAR%` references unexpanded.
+    pub user: usize,
+    /// System and User PATH joined with `;`, as stored.
+    pub combined_unexpanded: usize,
+    /// System and User PATH joined with `;` and `%VAR%` expanded, as a new process sees it.
+    pub combined: usize,
+    pub legacy_limit: usize,
+    pub max: usize,
+}
+
+impl Default for LengthBudget {
+    fn default() -> Self {
+        Self {
+            system: 0,
+            user: 0,
+            combined_unexpanded: 0,
+            combined: 0,
+            legacy_limit: LEGACY_PATH_LIMIT,
+            max: MAX_PATH_LENGTH,
+        }
+    }
+}
+
+impl LengthBudget {
+    /// Would Windows cut off the end of the PATH?
+    pub fn is_truncated(&self) -> bool {
+        self.combined > self.max
+    }
+
+    /// Does the PATH fit both limits? The legacy limit is checked against the stored form,
+    /// which is what compaction can shorten.
+    pub fn fits(&self) -> bool {
+        self.combined_unexpanded <= self.legacy_limit && !self.is_truncated()
+    }
+
+    /// Is the expanded PATH close to, but not yet over, the legacy limit?
+    pub fn approaching_legacy_limit(&self) -> bool {
+        approaching(self.combined, self.legacy_limit)
+    }
+
+    /// Is the expanded PATH close to, but not yet over, the hard limit?
+    pub fn approaching_max(&self) -> bool {
+        approaching(self.combined, self.max)
+    }
+}
+
+/// Is `length` within `HEADROOM_PERCENT` of `limit`, without exceeding it?
+fn approaching(length: usize, limit: usize) -> bool {
+    length <= limit && length * 100 >= limit * (100 - HEADROOM_PERCENT)
+}
+
+/// Measures a pair of PATH values, expanding `%VAR%` references through `system`.
+pub fn measure(system: &impl SystemOps, system_path: &str, user_path: &str) -> LengthBudget {
+    let join = |values: [&str; 2]| -> String {
+        values.iter().filter(|v| !v.is_empty()).copied().collect::<Vec<_>>().join(";")
+    };
+    let expanded = [system.expand_env_vars(system_path), system.expand_env_vars(user_path)];
+    LengthBudget {
+        system: system_path.chars().count(),
+        user: user_path.chars().count(),
+        combined_unexpanded: join([system_path, user_path]).chars().count(),
+        combined: join([&expanded[0], &expanded[1]]).chars().count(),
+        legacy_limit: LEGACY_PATH_LIMIT,
+        max: MAX_PATH_LENGTH,
+    }
+}
+
+/// The shortest `%VAR%`-relative spelling of `entry`, e.g. `%LOCALAPPDATA%\Programs\Python`,
+/// if one is shorter than the entry itself. Entries already using a variable are left alone.
+pub fn variable_form(entry: &str, lookup: impl Fn(&str) -> Option<String>) -> Option<String> {
+    if entry.contains('%') {
+        return None;
+    }
+    COMPACTION_VARIABLES.iter()
+        .filter_map(|name| {
+            let value = lookup(name)?;
+            let value = value.trim_end_matches('\\');
+            let prefix = entry.get(..value.len())?;
+            let rest = &entry[value.len()..];
+            let at_boundary = rest.is_empty() || rest.starts_with('\\');
+            (!value.is_empty() && at_boundary && prefix.eq_ignore_ascii_case(value))
+                .then(|| format!("%{}%{}", name, rest))
+        })
+        .min_by_key(|form| form.len())
+        .filter(|form| form.len() < entry.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    #[test]
+    fn test_measure_expanded_and_unexpanded() {
+        let system = MockSystem::new();
+        system.set_env("USERPROFILE", r"C:\Users\someone");
+
+        let budget = measure(&system, r"C:\Windows", r"%USERPROFILE%\bin");
+
+        assert_eq!(budget.system, 10);
+        assert_eq!(budget.user, 17);
+        assert_eq!(budget.combined_unexpanded, r"C:\Windows;%USERPROFILE%\bin".len());
+        assert_eq!(budget.combined, r"C:\Windows;C:\Users\someone\bin".len());
+        assert!(budget.fits());
+        assert_eq!(measure(&system, "", r"C:\a").combined, 4, "No separator for an empty PATH");
+
+        assert!(approaching(1900, LEGACY_PATH_LIMIT));
+        assert!(!approaching(1500, LEGACY_PATH_LIMIT));
+        assert!(!approaching(2048, LEGACY_PATH_LIMIT), "Past the limit is not approaching it");
+    }
+
+    #[test]
+    fn test_variable_form() {
+        let lookup = |name: &str| match name {
+            "USERPROFILE" => Some(r"C:\Users\someone".to_string()),
+            "LOCALAPPDATA" => Some(r"C:\Users\someone\AppData\Local".to_string()),
+            "ProgramFiles" => Some(r"C:\Program Files\".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(variable_form(r"C:\Users\someone\.cargo\bin", lookup).as_deref(), Some(r"%USERPROFILE%\.cargo\bin"));
+        assert_eq!(variable_form(r"c:\users\someone\appdata\local\Programs\Python", lookup).as_deref(), Some(r"%LOCALAPPDATA%\Programs\Python"));
+        assert_eq!(variable_form(r"C:\Program Files\Git\cmd", lookup).as_deref(), Some(r"%ProgramFiles%\Git\cmd"));
+        assert_eq!(variable_form(r"C:\Users\someone2\bin", lookup), None, "Only whole directory names match");
+        assert_eq!(variable_form(r"%USERPROFILE%\bin", lookup), None);
+        assert_eq!(variable_form(r"D:\tools", lookup), None);
+    }
+}
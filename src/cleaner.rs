@@ -2,341 +2,706 @@
 //!
 //! This module contains the core business logic for Wanderlust. It is responsible for:
 //! 1. Orchestrating the discovery of tools (`heal_path`).
-//! 2. Constructing the optimal PATH string (`build_minimal_path`).
-//! 3. Safely applying changes to the Windows Registry (`apply_path`).
-//! 4. Verifying system stability and rolling back if necessary.
+//! 2. Planning the change as data (`plan_healing` -> `plan::HealPlan`).
+//! 3. Constructing the optimal PATH string (`build_minimal_path`).
+//! 4. Safely applying a plan to the Windows Registry (`apply_plan`, `apply_path`).
+//! 5. Verifying system stability and rolling back if necessary.
 //!
 //! It also handles the generation of POSIX-compatible cache files for Git Bash / MSYS2 integration.
 
-use std::collections::{HashSet, HashMap};
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashSet, HashMap};
+use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
 use anyhow::{Result, bail};
-use log::{info, debug, warn, error};
-use windows_registry::CURRENT_USER;
+use log::{info, warn, error};
+use crate::backup;
+use crate::budget;
+use crate::config::{Config, PathOrder, PathRules};
 use crate::discovery;
 use crate::invariant_ppt::*;
-use crate::system::{SystemOps, WindowsSystem};
+use crate::java;
+use crate::normalize;
+use crate::plan::{Action, HealPlan, PlanChange, Rank, RankedEntry, Reason, Scope};
+use crate::rebalance;
+use crate::state::HealState;
+use crate::system::{PathValue, SystemOps};
+#[cfg(windows)]
+use crate::system::WindowsSystem;
 
 /// The main entry point for the healing logic.
 ///
 /// # Arguments
 ///
 /// * `dry_run` - If true, calculates the new PATH and prints it, but does NOT modify the Registry or file system.
+/// * `plan_out` - If set, writes the plan to this JSON file for review instead of applying it.
+/// * `config` - Settings that shape the new User PATH (e.g. its order).
 ///
 /// # Returns
 ///
 /// Returns `Ok(())` on success, or an `anyhow::Result` error if Registry access fails or verification breaks.
-pub fn heal_path(dry_run: bool) -> Result<()> {
+#[cfg(windows)]
+pub fn heal_path(dry_run: bool, plan_out: Option<&Path>, config: &Config) -> Result<()> {
     let system = WindowsSystem;
     
     // Discovery runs silently - user doesn't need to see this
-    let candidates_map = discovery::discover_candidates();
+    let candidates_map = discovery::discover_candidates(&system, config);
     
-    // First, clean the SYSTEM PATH (HKLM) - this removes duplicates from the machine-wide config
-    // Silently skip if not admin - the dry-run output will explain
-    let _ = clean_system_path(&system, dry_run);
+    if let Some(plan_file) = plan_out {
+        return export_plan(&candidates_map, &system, config, plan_file);
+    }
     
-    // Then heal the User PATH with discovery results
-    run_healing(&candidates_map, &system, dry_run)
+    // Plan both the SYSTEM PATH (HKLM) dedupe and the User PATH rebuild, then apply
+    run_healing(&candidates_map, &system, config, dry_run)
 }
 
-/// Cleans the System PATH (HKLM) by removing duplicates.
-/// This only deduplicates - it does NOT add new paths or remove valid ones.
-/// Requires Admin privileges.
-fn clean_system_path(system: &impl SystemOps, dry_run: bool) -> Result<()> {
-    let system_path = system.read_system_path_registry()?;
-    
-    let mut seen: HashSet<String> = HashSet::new();
-    let mut cleaned: Vec<String> = Vec::new();
+/// Applies a reviewed plan file written by `heal --plan-out`.
+///
+/// Refuses if either PATH has changed since the plan was made (see `apply_plan`).
+#[cfg(windows)]
+pub fn apply_plan_file(plan_file: &Path) -> Result<()> {
+    let plan = HealPlan::load(plan_file)?;
+    apply_plan(&WindowsSystem, &plan)?;
+    info!("Successfully applied plan {:?}", plan_file);
+    Ok(())
+}
+
+/// Removes duplicate and dead System PATH entries, keeping the first occurrence and its original spelling.
+///
+/// Entries are compared in canonical form, so `%SystemRoot%\system32\` duplicates `C:\Windows\system32`.
+/// `is_dead` decides, by canonical key, which missing entries have run out of grace (see `state::HealState`).
+///
+/// Returns the new value and one `PlanChange` per removed entry. If nothing is removed,
+/// the original value is returned untouched (stray empty segments are not worth a write).
+fn clean_system_entries(
+    system: &impl SystemOps,
+    system_path: &str,
+    is_dead: impl Fn(&Path) -> bool,
+) -> (String, Vec<PlanChange>) {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut cleaned: Vec<&str> = Vec::new();
+    let mut removed: Vec<PlanChange> = Vec::new();
     
     for part in system_path.split(';') {
         if part.is_empty() { continue; }
-        let normalized = part.to_lowercase();
-        if !seen.contains(&normalized) {
-            seen.insert(normalized);
-            cleaned.push(part.to_string()); // Keep original casing
-        }
-    }
-    
-    let new_system_path = cleaned.join(";");
-    
-    let old_count = system_path.split(';').filter(|s| !s.is_empty()).count();
-    let new_count = cleaned.len();
-    
-    if old_count == new_count {
-        info!("System PATH already clean ({} entries)", new_count);
-        return Ok(());
+        let norm = entry_key(system, part);
+        let reason = if !seen.insert(norm.clone()) {
+            Reason::Duplicate
+        } else if is_dead(&norm) {
+            Reason::MissingDirectory
+        } else {
+            cleaned.push(part); // Keep original spelling, including %VAR% references
+            continue;
+        };
+        removed.push(PlanChange { scope: Scope::System, action: Action::Remove, path: part.to_string(), reason });
     }
     
-    info!("System PATH: {} -> {} entries (removing {} duplicates)", old_count, new_count, old_count - new_count);
-    
-    if dry_run {
-        println!("--- DRY RUN: System PATH would be cleaned ---");
-        return Ok(());
+    if removed.is_empty() {
+        return (system_path.to_string(), removed);
     }
-    
-    system.write_system_path_registry(&new_system_path)?;
-    info!("System PATH cleaned successfully");
-    Ok(())
+    (cleaned.join(";"), removed)
 }
 
-/// Core logic for healing, decoupled from the concrete System for testing.
-pub fn run_healing(
+/// Computes a `HealPlan` from the discovery results without modifying anything.
+///
+/// Only reads from `system`: the current User and System PATH, and whether removed
+/// directories still exist (to explain why they are going away). Entries whose directory
+/// is missing are only removed once `state` counts them as dead (see `observe_missing_entries`).
+pub fn plan_healing(
     candidates_map: &HashMap<String, Vec<discovery::Candidate>>,
     system: &impl SystemOps,
-    dry_run: bool
-) -> Result<()> {
-    // Get current User PATH for comparison
-    let current_user_path = system.read_user_path_registry().unwrap_or_default();
-    let current_entries: HashSet<String> = current_user_path.split(';')
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_lowercase())
-        .collect();
+    config: &Config,
+    state: &HealState,
+) -> HealPlan {
+    let user_path_before = system.read_user_path_registry().unwrap_or_default();
+    let system_path_before = system.read_system_path_registry().unwrap_or_default();
     
-    let new_path_string = build_minimal_path(candidates_map);
+    // Scopes switched off in the config are planned as "leave exactly as is"
+    let (system_path_after, mut system_changes) = if config.scopes.system {
+        clean_system_entries(system, &system_path_before, |norm| {
+            state.is_dead(&norm.to_string_lossy(), &config.dead_entries)
+        })
+    } else {
+        (system_path_before.clone(), Vec::new())
+    };
+    let user_path_after = if config.scopes.user {
+        build_minimal_path(candidates_map, system, config, state)
+    } else {
+        user_path_before.clone()
+    };
+    let rules = config.path_rules(|name| system.lookup_env_var(name));
+    let (system_path_after, user_path_after, moved) = if config.rebalance && config.scopes.user && config.scopes.system {
+        let result = rebalance::rebalance(
+            &system_path_after,
+            &user_path_after,
+            &user_path_before,
+            |entry| entry_key(system, entry).to_string_lossy().to_string(),
+            |key| rules.pin_rank(key).is_some(),
+            |name| system.lookup_env_var(name),
+        );
+        (result.system_path, result.user_path, result.migrations)
+    } else {
+        (system_path_after, user_path_after, Vec::new())
+    };
+    let moved_from = |scope: Scope| -> HashSet<PathBuf> {
+        moved.iter().filter(|m| m.from == scope).map(|m| entry_key(system, &m.entry)).collect()
+    };
+    let (moved_to_user, moved_to_system) = (moved_from(Scope::System), moved_from(Scope::User));
+    let jdk_bin = chosen_jdk_bin(candidates_map);
+    let system_path_after = match &jdk_bin {
+        Some(bin) if config.scopes.system => {
+            let (system_path, changes) = put_ahead_of_javapath(system, &system_path_after, bin);
+            system_changes.extend(changes);
+            system_path
+        }
+        _ => system_path_after,
+    };
+    let (user_path_after, compacted) = if config.scopes.user && config.compact {
+        compact_user_path(system, &system_path_after, &user_path_after, &rules)
+    } else {
+        (user_path_after, HashMap::new())
+    };
     
-    let new_entries: HashSet<String> = new_path_string.split(';')
+    let system_entries: HashSet<PathBuf> = system_path_before.split(';')
         .filter(|s| !s.is_empty())
-        .map(|s| s.to_lowercase())
+        .map(|s| entry_key(system, s))
         .collect();
-    
-    // Calculate what's changing
-    let removing: Vec<&str> = current_user_path.split(';')
+    let before_entries: HashSet<PathBuf> = user_path_before.split(';')
         .filter(|s| !s.is_empty())
-        .filter(|s| !new_entries.contains(&s.to_lowercase()))
+        .map(|s| entry_key(system, s))
         .collect();
-    
-    let adding: Vec<&str> = new_path_string.split(';')
+    let after_entries: HashSet<PathBuf> = user_path_after.split(';')
         .filter(|s| !s.is_empty())
-        .filter(|s| !current_entries.contains(&s.to_lowercase()))
+        .map(|s| entry_key(system, s))
         .collect();
     
-    if dry_run {
-        println!();
-        println!("═══════════════════════════════════════════════════════════════");
-        println!("                   What Wanderlust Will Do");
-        println!("═══════════════════════════════════════════════════════════════");
-        println!();
-        
-        // System PATH status
-        let system_path = system.read_system_path_registry().unwrap_or_default();
-        let sys_parts: Vec<&str> = system_path.split(';').filter(|s| !s.is_empty()).collect();
-        let sys_unique: HashSet<&str> = sys_parts.iter().cloned().collect();
-        let sys_dups = sys_parts.len() - sys_unique.len();
-        
-        println!("SYSTEM PATH (shared by all users):");
-        if sys_dups > 0 {
-            println!("  Currently has {} folders with {} duplicates.", sys_parts.len(), sys_dups);
-            println!("  → Will remove duplicates (requires running as Administrator)");
-        } else {
-            println!("  ✓ Already clean ({} folders, no duplicates)", sys_parts.len());
-        }
-        
-        // User PATH changes
-        let before_count = current_user_path.split(';').filter(|s| !s.is_empty()).count();
-        let after_count = new_path_string.split(';').filter(|s| !s.is_empty()).count();
-        
-        println!();
-        println!("USER PATH (just your tools):");
-        println!("  Currently: {} folders", before_count);
-        println!("  After:     {} folders", after_count);
-        
-        if !removing.is_empty() {
-            println!();
-            println!("  REMOVING {} folders (already in System PATH or duplicates):", removing.len());
-            for p in &removing {
-                println!("    ✕ {}", p);
+    let mut changes = Vec::new();
+    
+    if config.scopes.user {
+        // Removals, in current User PATH order
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        for entry in user_path_before.split(';').filter(|s| !s.is_empty()) {
+            let norm = entry_key(system, entry);
+            let first = seen.insert(norm.clone());
+            if let Some(reason) = compacted.get(&norm).filter(|_| first) {
+                changes.push(PlanChange { scope: Scope::User, action: Action::Remove, path: entry.to_string(), reason: reason.clone() });
+                continue;
+            }
+            if first && after_entries.contains(&norm) {
+                continue;
             }
+            let reason = if first && moved_to_system.contains(&norm) {
+                Reason::Moved { from: Scope::User, to: Scope::System }
+            } else if system_entries.contains(&norm) {
+                Reason::DuplicateOfSystem
+            } else if !first {
+                Reason::Duplicate
+            } else if rules.is_blocked(&norm.to_string_lossy()) {
+                Reason::Blocked
+            } else {
+                // Existing entries are only ever dropped as duplicates, blocked or dead
+                Reason::MissingDirectory
+            };
+            changes.push(PlanChange { scope: Scope::User, action: Action::Remove, path: entry.to_string(), reason });
         }
-        
-        if !adding.is_empty() {
-            println!();
-            println!("  ADDING {} folders (discovered tools not yet in PATH):", adding.len());
-            for p in &adding {
-                println!("    + {}", p);
+
+        // Additions, in new User PATH order
+        for entry in user_path_after.split(';').filter(|s| !s.is_empty()) {
+            let norm = entry_key(system, entry);
+            if before_entries.contains(&norm) {
+                continue;
             }
+            let sources = discovery_sources(candidates_map, system, &norm);
+            let reason = if moved_to_user.contains(&norm) {
+                Reason::Moved { from: Scope::System, to: Scope::User }
+            } else if sources.is_empty() && rules.pin_rank(&norm.to_string_lossy()).is_some() {
+                Reason::Pinned
+            } else {
+                Reason::Discovered { sources }
+            };
+            changes.push(PlanChange { scope: Scope::User, action: Action::Add, path: entry.to_string(), reason });
         }
-        
-        println!();
-        println!("───────────────────────────────────────────────────────────────");
-        if removing.is_empty() && adding.is_empty() && sys_dups == 0 {
-            println!();
-            println!("✓ Nothing to do! Your PATH is already optimal.");
-        } else {
-            println!();
-            println!("This is a preview. Run 'wanderlust heal' to apply changes.");
-            println!("(Changes only affect new terminals. Current terminal keeps old PATH.)");
+    }
+    
+    for m in &moved {
+        let (action, from, to) = match m.from {
+            Scope::System => (Action::Remove, Scope::System, Scope::User),
+            Scope::User => (Action::Add, Scope::User, Scope::System),
+        };
+        system_changes.push(PlanChange { scope: Scope::System, action, path: m.entry.clone(), reason: Reason::Moved { from, to } });
+    }
+    changes.extend(system_changes);
+
+    let user_order = user_path_after.split(';')
+        .filter(|s| !s.is_empty())
+        .map(|entry| RankedEntry {
+            path: entry.to_string(),
+            rank: entry_rank(candidates_map, system, config, &rules, &entry_key(system, entry)),
+        })
+        .collect();
+    
+    let java_home_before = system.read_user_env_var("JAVA_HOME");
+    let java_home_after = match &jdk_bin {
+        Some(bin) if config.java.set_java_home => {
+            bin.trim_end_matches(['\\', '/']).rsplit_once(['\\', '/']).map(|(home, _)| home.to_string())
         }
-        println!();
-        
-        return Ok(());
+        _ => java_home_before.clone(),
+    };
+
+    HealPlan {
+        user_order,
+        length_before: budget::measure(system, &system_path_before, &user_path_before),
+        length_after: budget::measure(system, &system_path_after, &user_path_after),
+        user_path_before,
+        user_path_after,
+        system_path_before,
+        system_path_after,
+        changes,
+        java_home_before,
+        java_home_after,
     }
+}
 
-    // Generate and write POSIX path for Git Bash / MSYS integration
-    // This file contains the COMPLETE PATH (System + User) in POSIX format
-    if let Some(user_dirs) = directories::UserDirs::new() {
-        // Get System PATH and convert to POSIX
-        let system_path = system.read_system_path_registry().unwrap_or_default();
-        let system_posix: Vec<String> = system_path.split(';')
-            .filter(|s| !s.is_empty())
-            .map(|p| win_to_posix(p))
-            .collect();
-        
-        // Convert User PATH to POSIX
-        let user_posix: Vec<String> = new_path_string.split(';')
-            .filter(|s| !s.is_empty())
-            .map(|p| win_to_posix(p))
-            .collect();
-        
-        // Combine: System first, then User (matches Windows behavior)
-        let full_posix = [system_posix, user_posix].concat().join(":");
-        
-        let posix_file = user_dirs.home_dir().join(".wanderlust_posix");
-        if let Ok(mut f) = File::create(&posix_file) {
-             let _ = writeln!(f, "{}", full_posix);
-             info!("Wrote POSIX path to {:?} ({} entries)", posix_file, full_posix.matches(':').count() + 1);
+/// The `bin` directory of the JDK discovery chose (see `java::scan`), if any.
+fn chosen_jdk_bin(candidates_map: &HashMap<String, Vec<discovery::Candidate>>) -> Option<String> {
+    candidates_map.values()
+        .flatten()
+        .find(|c| c.source == java::SOURCE)
+        .map(|c| c.path.to_string_lossy().to_string())
+}
+
+/// Puts the JDK directory `bin` into the System PATH right before Oracle's `javapath` shims,
+/// which would otherwise run instead (the System PATH is searched before the User PATH).
+///
+/// Nothing changes if there is no `javapath`, `bin` already precedes it, or `bin` is inside the
+/// user's profile (it does not belong in the System PATH, see `rebalance::owning_scope`).
+/// A copy of `bin` later in the System PATH moves up. A copy in the User PATH stays until a heal
+/// finds `bin` in the System PATH, so a heal without Administrator rights loses nothing.
+/// Returns the new System PATH and its changes.
+fn put_ahead_of_javapath(system: &impl SystemOps, system_path: &str, bin: &str) -> (String, Vec<PlanChange>) {
+    let unchanged = (system_path.to_string(), Vec::new());
+    let key = entry_key(system, bin);
+    let mut entries: Vec<&str> = system_path.split(';').filter(|s| !s.is_empty()).collect();
+    let Some(javapath) = entries.iter().position(|e| java::is_javapath(&entry_key(system, e).to_string_lossy())) else {
+        return unchanged;
+    };
+    if entries[..javapath].iter().any(|e| entry_key(system, e) == key) {
+        return unchanged;
+    }
+    if rebalance::owning_scope(&key.to_string_lossy(), |name| system.lookup_env_var(name)) == Some(Scope::User) {
+        warn!("{} is in your profile, so it can't go into the System PATH ahead of {}", bin, entries[javapath]);
+        return unchanged;
+    }
+
+    let mut changes = Vec::new();
+    if let Some(later) = entries.iter().position(|e| entry_key(system, e) == key) {
+        let entry = entries.remove(later);
+        changes.push(PlanChange { scope: Scope::System, action: Action::Remove, path: entry.to_string(), reason: Reason::AheadOfJavapath });
+    }
+    info!("Putting {} ahead of {} in the System PATH", bin, entries[javapath]);
+    entries.insert(javapath, bin);
+    changes.push(PlanChange { scope: Scope::System, action: Action::Add, path: bin.to_string(), reason: Reason::AheadOfJavapath });
+    (entries.join(";"), changes)
+}
+
+/// Shortens a new User PATH until the combined PATH fits the length limits (`config.compact`).
+///
+/// First every entry that has one is rewritten to its `%VAR%`-relative form (see `budget::variable_form`).
+/// If that is not enough, entries are dropped from the end, where they matter least because
+/// Windows searches the PATH in order. Pinned entries are never dropped, and nothing is dropped
+/// if the System PATH alone is over the limits.
+///
+/// Returns the new value and, by canonical key, why each existing entry changed.
+fn compact_user_path(
+    system: &impl SystemOps,
+    system_path: &str,
+    user_path: &str,
+    rules: &PathRules,
+) -> (String, HashMap<PathBuf, Reason>) {
+    let fits = |entries: &[String]| budget::measure(system, system_path, &entries.join(";")).fits();
+    let mut entries: Vec<String> = user_path.split(';').filter(|s| !s.is_empty()).map(str::to_string).collect();
+    let mut changed = HashMap::new();
+    if fits(&entries) {
+        return (user_path.to_string(), changed);
+    }
+
+    for entry in entries.iter_mut() {
+        if let Some(short) = budget::variable_form(entry, |name| system.lookup_env_var(name)) {
+            changed.insert(entry_key(system, entry), Reason::Shortened { to: short.clone() });
+            *entry = short;
         }
     }
 
-    // Apply the changes to the system
-    apply_path(system, &new_path_string)?;
+    if !fits(&[]) {
+        warn!("The System PATH alone is over the length limit; not dropping User PATH entries to make room");
+        return (entries.join(";"), changed);
+    }
+    while !fits(&entries) {
+        let droppable = entries.iter().rposition(|e| rules.pin_rank(&entry_key(system, e).to_string_lossy()).is_none());
+        let Some(i) = droppable else { break };
+        let dropped = entries.remove(i);
+        changed.insert(entry_key(system, &dropped), Reason::OverLengthBudget);
+    }
+    (entries.join(";"), changed)
+}
+
+/// What places the entry with key `norm` in the User PATH: its pin, else its best-ranked discovery
+/// source. This is the same ranking `build_minimal_path` orders by.
+fn entry_rank(
+    candidates_map: &HashMap<String, Vec<discovery::Candidate>>,
+    system: &impl SystemOps,
+    config: &Config,
+    rules: &PathRules,
+    norm: &Path,
+) -> Rank {
+    if let Some(index) = rules.pin_rank(&norm.to_string_lossy()) {
+        return Rank::Pinned { pin: index + 1 };
+    }
+    discovery_sources(candidates_map, system, norm).into_iter()
+        .map(|source| (config.source_rank(&source), source))
+        .min()
+        .map_or(Rank::Unranked, |(rank, source)| Rank::Source { source, rank: rank + 1 })
+}
+
+/// All discovery sources that reported the entry with key `norm`, sorted and deduplicated.
+fn discovery_sources(
+    candidates_map: &HashMap<String, Vec<discovery::Candidate>>,
+    system: &impl SystemOps,
+    norm: &Path,
+) -> Vec<String> {
+    let sources: BTreeSet<&str> = candidates_map.values()
+        .flatten()
+        .filter(|c| entry_key(system, &c.path.to_string_lossy()) == norm)
+        .map(|c| c.source.as_str())
+        .collect();
+    sources.into_iter().map(str::to_string).collect()
+}
+
+/// Core logic for healing, decoupled from the concrete System for testing.
+pub fn run_healing(
+    candidates_map: &HashMap<String, Vec<discovery::Candidate>>,
+    system: &impl SystemOps,
+    config: &Config,
+    dry_run: bool
+) -> Result<()> {
+    let state = observe_missing_entries(system, config, unix_now());
+    let plan = plan_healing(candidates_map, system, config, &state);
+    
+    if dry_run {
+        print_plan(&plan);
+        return Ok(());
+    }
+
+    // A dry run previews this heal's view of missing entries, but only real heals count towards their grace
+    save_state(system, &state);
+    apply_plan(system, &plan)?;
     info!("Successfully healed PATH!");
     
     Ok(())
 }
 
-/// Runs a "Doctor" check to report on the health of the CURRENT and STORED path.
+/// Plans a heal and writes it to `plan_file` for review (`heal --plan-out`), without applying it.
 ///
-/// This does not modify the system.
-pub fn doctor() -> Result<()> {
-    let system = WindowsSystem;
-    
+/// Like a dry run, exporting a plan does not count towards the grace of missing entries.
+pub fn export_plan(
+    candidates_map: &HashMap<String, Vec<discovery::Candidate>>,
+    system: &impl SystemOps,
+    config: &Config,
+    plan_file: &Path,
+) -> Result<()> {
+    let state = observe_missing_entries(system, config, unix_now());
+    let plan = plan_healing(candidates_map, system, config, &state);
+    print_plan(&plan);
+    plan.save(plan_file)?;
+    info!("Wrote plan to {:?}. Review it, then run 'wanderlust apply {}'", plan_file, plan_file.display());
+    Ok(())
+}
+
+/// Loads the persistent state and records which entries of the PATH scopes being healed
+/// point at a directory that does not exist at Unix time `now`.
+fn observe_missing_entries(system: &impl SystemOps, config: &Config, now: u64) -> HealState {
+    let mut paths = Vec::new();
+    if config.scopes.user {
+        paths.push(system.read_user_path_registry().unwrap_or_default());
+    }
+    if config.scopes.system {
+        paths.push(system.read_system_path_registry().unwrap_or_default());
+    }
+    let missing: Vec<String> = paths.iter()
+        .flat_map(|path| path.split(';'))
+        .filter(|entry| !entry.is_empty() && !directory_exists(system, entry))
+        .map(|entry| entry_key(system, entry).to_string_lossy().to_string())
+        .collect();
+
+    let mut state = HealState::load(system);
+    state.observe(missing, now);
+    state
+}
+
+/// Saves the state after a heal. Failing to do so only delays dead entry removal, so it is not fatal.
+fn save_state(system: &impl SystemOps, state: &HealState) {
+    if let Err(e) = state.save(system) {
+        warn!("Could not save state (missing entries will be kept longer): {}", e);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Prints a human-readable preview of a plan.
+pub fn print_plan(plan: &HealPlan) {
     println!();
     println!("═══════════════════════════════════════════════════════════════");
-    println!("                      PATH Health Report");
+    println!("                   What Wanderlust Will Do");
     println!("═══════════════════════════════════════════════════════════════");
     println!();
-    println!("Windows has TWO places where PATH is stored:");
-    println!();
-
-    // 1. System PATH (HKLM)
-    let system_path = system.read_system_path_registry().unwrap_or_default();
-    let system_parts: Vec<&str> = system_path.split(';').filter(|s| !s.is_empty()).collect();
-    let system_unique: HashSet<&str> = system_parts.iter().cloned().collect();
-    let system_dups = system_parts.len() - system_unique.len();
     
-    println!("1. SYSTEM PATH ({} folders)", system_parts.len());
-    println!("   Shared by all users. Has Windows, Program Files, etc.");
-    if system_dups > 0 {
-        println!("   ⚠ Problem: {} duplicate entries (run as Admin to fix)", system_dups);
+    // System PATH status
+    let sys_count = plan.system_path_before.split(';').filter(|s| !s.is_empty()).count();
+    let sys_removing: Vec<&PlanChange> = plan.changes_for(Scope::System, Action::Remove).collect();
+    let sys_adding: Vec<&PlanChange> = plan.changes_for(Scope::System, Action::Add).collect();
+    
+    println!("SYSTEM PATH (shared by all users):");
+    if !sys_removing.is_empty() || !sys_adding.is_empty() {
+        println!("  Currently has {} folders; {} to remove, {} to add.", sys_count, sys_removing.len(), sys_adding.len());
+        println!("  → Will change it (requires running as Administrator)");
+        for c in &sys_removing {
+            println!("    ✕ {}  ({})", c.path, c.reason);
+        }
+        for c in &sys_adding {
+            println!("    + {}  ({})", c.path, c.reason);
+        }
     } else {
-        println!("   ✓ No duplicates");
+        println!("  ✓ Already clean ({} folders, no duplicates)", sys_count);
     }
-
-    // 2. User PATH (HKCU)
-    let hive = CURRENT_USER.open("Environment")?;
-    let user_path = hive.get_string("Path").unwrap_or_default();
-    let user_parts: Vec<&str> = user_path.split(';').filter(|s| !s.is_empty()).collect();
-    let user_unique: HashSet<&str> = user_parts.iter().cloned().collect();
-    let user_dups = user_parts.len() - user_unique.len();
+    
+    // User PATH changes
+    let before_count = plan.user_path_before.split(';').filter(|s| !s.is_empty()).count();
+    let after_count = plan.user_path_after.split(';').filter(|s| !s.is_empty()).count();
+    let removing: Vec<&PlanChange> = plan.changes_for(Scope::User, Action::Remove).collect();
+    let adding: Vec<&PlanChange> = plan.changes_for(Scope::User, Action::Add).collect();
     
     println!();
-    println!("2. USER PATH ({} folders)", user_parts.len());
-    println!("   Just for you. Has your tools like Python, Cargo, Scoop, etc.");
-    if user_dups > 0 {
-        println!("   ⚠ Problem: {} duplicate entries", user_dups);
-    } else {
-        println!("   ✓ No duplicates");
+    println!("USER PATH (just your tools):");
+    println!("  Currently: {} folders", before_count);
+    println!("  After:     {} folders", after_count);
+    
+    if !removing.is_empty() {
+        println!();
+        println!("  REMOVING {} folders:", removing.len());
+        for c in &removing {
+            println!("    ✕ {}  ({})", c.path, c.reason);
+        }
     }
-
-    // 3. Check for User entries that duplicate System entries
-    let system_normalized: HashSet<String> = system_parts.iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-    let overlap: Vec<&str> = user_parts.iter()
-        .filter(|p| system_normalized.contains(&p.to_lowercase()))
-        .cloned()
-        .collect();
     
-    if !overlap.is_empty() {
+    if !adding.is_empty() {
         println!();
-        println!("⚠ OVERLAP: {} folders appear in BOTH System and User PATH.", overlap.len());
-        println!("   This is wasteful. Examples:");
-        for p in overlap.iter().take(3) {
-            println!("     - {}", p);
+        println!("  ADDING {} folders (discovered tools not yet in PATH):", adding.len());
+        for c in &adding {
+            println!("    + {}  ({})", c.path, c.reason);
         }
-        if overlap.len() > 3 {
-            println!("     ... and {} more", overlap.len() - 3);
+    }
+
+    if plan.user_changed() && !plan.user_order.is_empty() {
+        println!();
+        println!("  NEW ORDER (when a command is in several folders, the first one wins):");
+        for (i, e) in plan.user_order.iter().enumerate() {
+            println!("    {:>2}. {}  [{}]", i + 1, e.path, e.rank);
         }
     }
+    
+    if plan.java_home_changed() {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "(not set)".to_string());
+        println!();
+        println!("JAVA_HOME (User environment):");
+        println!("  {} → {}", show(&plan.java_home_before), show(&plan.java_home_after));
+    }
 
-    // 4. Current terminal session explanation
-    println!();
-    println!("───────────────────────────────────────────────────────────────");
+    let (before, after) = (&plan.length_before, &plan.length_after);
     println!();
-    let total = system_parts.len() + user_parts.len();
-    println!("When you open a terminal, Windows combines both:");
-    println!("  System ({}) + User ({}) = {} folders to search for commands", 
-             system_parts.len(), user_parts.len(), total);
-    
-    if let Ok(current) = std::env::var("PATH") {
-        let current_count = current.split(';').filter(|s| !s.is_empty()).count();
-        if current_count != total {
-            println!();
-            println!("  Your current terminal has {} (Git Bash adds some extras).", current_count);
-        }
+    println!("LENGTH (System + User, expanded):");
+    println!("  {} → {} characters ({} → {} as stored)", before.combined, after.combined, before.combined_unexpanded, after.combined_unexpanded);
+    if after.is_truncated() {
+        println!("  ✕ Over the {} characters Windows allows; the end of the PATH would be cut off.", after.max);
+        println!("    Run 'wanderlust heal --compact' to shorten it.");
+    } else if after.combined > after.legacy_limit {
+        println!("  ⚠ Over the {} characters legacy tools (setx, some installers) handle.", after.legacy_limit);
     }
 
-    // 5. Summary
     println!();
     println!("───────────────────────────────────────────────────────────────");
-    if system_dups == 0 && user_dups == 0 && overlap.is_empty() {
+    if plan.changes.is_empty() && !plan.java_home_changed() {
         println!();
-        println!("✓ Your PATH is healthy! No action needed.");
+        println!("✓ Nothing to do! Your PATH is already optimal.");
     } else {
         println!();
-        println!("Run 'wanderlust heal' to fix the issues above.");
+        println!("This is a preview. Run 'wanderlust heal' to apply changes.");
+        println!("(Changes only affect new terminals. Current terminal keeps old PATH.)");
     }
     println!();
+}
 
+/// Applies a previously computed plan.
+///
+/// 0.  **Concurrency check**: Refuses if the Registry no longer holds the plan's "before" values,
+///     so a stale or reviewed plan can never clobber changes made after it was generated.
+/// 1.  **System PATH**: Written through `apply_path` only if the plan changes it. Failure (usually: not Administrator) is logged, not fatal,
+///     unless the plan moves entries from the User PATH into it: then the User PATH is left alone too, so they aren't lost.
+///     (Putting the chosen JDK ahead of `javapath` is skipped this way; its User PATH entry stays.)
+/// 2.  **POSIX cache**: Regenerated from whatever System PATH is now in effect.
+/// 3.  **User PATH**: Written through `apply_path` (backup, verify, rollback) only if the plan changes it.
+/// 4.  **JAVA_HOME**: Set in the User environment only if the plan changes it.
+pub fn apply_plan(system: &impl SystemOps, plan: &HealPlan) -> Result<()> {
+    check_plan_is_current(system, plan)?;
+
+    if plan.system_changed() {
+        match apply_path(system, Scope::System, &plan.system_path_after) {
+            Ok(()) => info!("System PATH cleaned successfully"),
+            Err(e) if plan.changes_for(Scope::System, Action::Add).any(|c| matches!(c.reason, Reason::Moved { .. })) => {
+                bail!("Could not write System PATH, so entries can't move into it; User PATH left unchanged too: {}", e);
+            }
+            Err(e) => warn!("Could not clean System PATH (requires Administrator, left unchanged): {}", e),
+        }
+    }
+
+    write_posix_cache(system, &plan.user_path_after);
+
+    if plan.user_changed() {
+        apply_path(system, Scope::User, &plan.user_path_after)?;
+    } else {
+        info!("User PATH already optimal, not rewriting it");
+    }
+
+    if let Some(java_home) = plan.java_home_after.as_ref().filter(|_| plan.java_home_changed()) {
+        system.write_user_env_var("JAVA_HOME", java_home)?;
+        let _ = system.broadcast_environment_change();
+        info!("JAVA_HOME set to {}", java_home);
+    }
+    
     Ok(())
 }
 
+/// Optimistic concurrency: the Registry must still hold exactly what the plan was computed from.
+fn check_plan_is_current(system: &impl SystemOps, plan: &HealPlan) -> Result<()> {
+    let user_now = system.read_user_path_registry().unwrap_or_default();
+    if user_now != plan.user_path_before {
+        bail!("User PATH has changed since this plan was made. Generate a new plan and review it again.");
+    }
+    let system_now = system.read_system_path_registry().unwrap_or_default();
+    if system_now != plan.system_path_before {
+        bail!("System PATH has changed since this plan was made. Generate a new plan and review it again.");
+    }
+    if system.read_user_env_var("JAVA_HOME") != plan.java_home_before {
+        bail!("JAVA_HOME has changed since this plan was made. Generate a new plan and review it again.");
+    }
+    Ok(())
+}
+
+/// Generates and writes the POSIX path for Git Bash / MSYS integration.
+///
+/// This file contains the COMPLETE PATH (System + User) in POSIX format.
+fn write_posix_cache(system: &impl SystemOps, user_path: &str) {
+    if let Some(user_dirs) = directories::UserDirs::new() {
+        // Get System PATH and convert to POSIX
+        let system_path = system.read_system_path_registry().unwrap_or_default();
+        // Shells don't understand %VAR%, so expand REG_EXPAND_SZ references first
+        let system_posix: Vec<String> = system_path.split(';')
+            .filter(|s| !s.is_empty())
+            .map(|p| win_to_posix(&system.expand_env_vars(p)))
+            .collect();
+        
+        // Convert User PATH to POSIX
+        let user_posix: Vec<String> = user_path.split(';')
+            .filter(|s| !s.is_empty())
+            .map(|p| win_to_posix(&system.expand_env_vars(p)))
+            .collect();
+        
+        // Combine: System first, then User (matches Windows behavior)
+        let full_posix = [system_posix, user_posix].concat().join(":");
+        
+        let posix_file = user_dirs.home_dir().join(".wanderlust_posix");
+        if let Ok(mut f) = File::create(&posix_file) {
+             let _ = writeln!(f, "{}", full_posix);
+             info!("Wrote POSIX path to {:?} ({} entries)", posix_file, full_posix.matches(':').count() + 1);
+        }
+    }
+}
+
 /// Constructs a minimal USER PATH string from discovered candidates.
 ///
 /// **The Immutable Logic:**
 /// 1.  **System PATH exclusion**: Don't duplicate anything already in HKLM System PATH.
-/// 2.  **Deduplication**: We normalize paths (lowercase) to ensure `C:\Win` and `c:\win` don't duplicate.
-/// 3.  **Discovery**: We append all discovered directories that contain executables.
-/// 4.  **No Windows paths**: System32, Windows, etc. belong in System PATH, not User PATH.
-fn build_minimal_path(map: &HashMap<String, Vec<discovery::Candidate>>) -> String {
+/// 2.  **Deduplication**: We compare canonical forms (see `normalize::canonicalize`) to ensure
+///     `C:\Win`, `c:\win\` and `%SystemRoot%` don't duplicate.
+/// 3.  **Discovery**: We include all discovered directories that contain executables.
+/// 4.  **No Windows paths**: Discovered System32, Windows, etc. belong in System PATH, not User PATH.
+/// 5.  **Spelling**: Entries already in the User PATH are written back exactly as they were,
+///     new ones as pinned or as discovered. Canonical keys are never written.
+/// 6.  **Order**: Entries are ordered by `config.order` (see `order_entries`), pinned entries first.
+/// 7.  **Rules**: Blocked entries are dropped; pins without wildcards are added even if undiscovered.
+/// 8.  **Existing entries**: Kept unless they duplicate another entry, are blocked, or are dead.
+///     A directory without executables may still hold `.ps1` scripts or DLLs the loader needs,
+///     and one that is missing is kept until `state` counts it as dead.
+///
+/// The System PATH is read through `system`, never from the host registry directly.
+fn build_minimal_path(
+    map: &HashMap<String, Vec<discovery::Candidate>>,
+    system: &impl SystemOps,
+    config: &Config,
+    state: &HealState,
+) -> String {
     // Read System PATH to avoid duplicating entries
-    let system = WindowsSystem;
     let system_path_entries: HashSet<PathBuf> = system.read_system_path_registry()
         .unwrap_or_default()
         .split(';')
         .filter(|s| !s.is_empty())
-        .map(|s| normalize_path(&PathBuf::from(s)))
+        .map(|s| entry_key(system, s))
         .collect();
     
+    // Entries already in the User PATH keep their exact spelling,
+    // so `%USERPROFILE%\.cargo\bin` is not replaced by its expansion.
+    // Their position is remembered too, because order decides which `node.exe` wins.
+    // New entries are written as pinned, or else as discovered; keys are only for comparing.
+    let rules = config.path_rules(|name| system.lookup_env_var(name));
+    let user_path = system.read_user_path_registry().unwrap_or_default();
+    let existing: Vec<&str> = user_path.split(';').filter(|s| !s.is_empty()).collect();
+    let mut spelling: HashMap<PathBuf, String> = HashMap::new();
+    let mut existing_position: HashMap<PathBuf, usize> = HashMap::new();
+    for (i, entry) in existing.iter().enumerate() {
+        let norm = entry_key(system, entry);
+        existing_position.entry(norm.clone()).or_insert(i);
+        spelling.entry(norm).or_insert_with(|| entry.to_string());
+    }
+    for pin in rules.literal_pins() {
+        spelling.entry(entry_key(system, pin)).or_insert_with(|| pin.to_string());
+    }
+    
     info!("System PATH has {} entries (will not duplicate these)", system_path_entries.len());
 
     let mut seen_paths: HashSet<PathBuf> = system_path_entries.clone();
     let mut user_paths: Vec<PathBuf> = Vec::new();
+    let mut best_rank: HashMap<PathBuf, usize> = HashMap::new();
     
     // Collect all unique directories from discovery that aren't in System PATH
     for candidates in map.values() {
         for candidate in candidates {
-            let norm = normalize_path(&candidate.path);
+            let norm = entry_key(system, &candidate.path.to_string_lossy());
             
             // Skip Windows system directories - they belong in System PATH
-            let path_str = norm.to_string_lossy().to_lowercase();
-            if path_str.contains("\\windows\\") || path_str.starts_with("c:\\windows") {
+            if is_windows_dir(&norm.to_string_lossy()) {
+                continue;
+            }
+            
+            if rules.is_blocked(&norm.to_string_lossy()) {
                 continue;
             }
             
+            let rank = config.source_rank(&candidate.source);
+            best_rank.entry(norm.clone()).and_modify(|r| *r = (*r).min(rank)).or_insert(rank);
+            spelling.entry(norm.clone()).or_insert_with(|| candidate.path.to_string_lossy().to_string());
+            
             if !seen_paths.contains(&norm) {
                 seen_paths.insert(norm.clone());
                 user_paths.push(norm);
@@ -344,8 +709,37 @@ fn build_minimal_path(map: &HashMap<String, Vec<discovery::Candidate>>) -> Strin
         }
     }
 
-    // Sort to ensure deterministic output
-    user_paths.sort();
+    // Existing entries stay even if discovery found nothing in them: the directory
+    // may hold DLLs or scripts, or sit on a drive that is unplugged right now.
+    // Only the first spelling of each entry counts; later ones are duplicates either way.
+    for (i, entry) in existing.iter().enumerate() {
+        let norm = entry_key(system, entry);
+        if existing_position.get(&norm) != Some(&i) {
+            continue;
+        }
+        let key = norm.to_string_lossy();
+        if rules.is_blocked(&key) {
+            continue;
+        }
+        if state.is_dead(&key, &config.dead_entries) && !directory_exists(system, entry) {
+            continue;
+        }
+        if seen_paths.insert(norm.clone()) {
+            user_paths.push(norm);
+        }
+    }
+
+    // Pins are kept even if discovery never found them (e.g. a network share)
+    for pin in rules.literal_pins() {
+        let norm = entry_key(system, pin);
+        if seen_paths.insert(norm.clone()) {
+            user_paths.push(norm);
+        }
+    }
+
+    order_entries(&mut user_paths, config.order, &existing_position, &best_rank);
+    // Stable, so unpinned entries keep the strategy's order
+    user_paths.sort_by_key(|p| rules.pin_rank(&p.to_string_lossy()).unwrap_or(usize::MAX));
 
     // INVARIANT CHECK:
     // User PATH can be empty if everything is in System PATH - that's actually ideal!
@@ -356,22 +750,51 @@ fn build_minimal_path(map: &HashMap<String, Vec<discovery::Candidate>>) -> Strin
     });
     
     if !user_paths.is_empty() {
-        assert_invariant(has_user_tools || user_paths.len() > 0, "User PATH should contain user-specific paths", Some("Cleaner"));
+        assert_invariant(has_user_tools || !user_paths.is_empty(), "User PATH should contain user-specific paths", Some("Cleaner"));
     }
 
     // Join with Windows standard separator ';'
     user_paths.iter()
-        .map(|p| p.to_string_lossy().to_string())
+        .map(|p| spelling.get(p).cloned().unwrap_or_else(|| p.to_string_lossy().to_string()))
         .collect::<Vec<_>>()
         .join(";")
 }
 
-/// Normalizes a path for comparison.
+/// Sorts the new User PATH entries (canonical keys) according to `order`.
+///
+/// `position` is each entry's index in the current User PATH; entries not in it count as new.
+/// `rank` is each entry's best discovery source rank. Ties always fall back to current position,
+/// then to the key itself, so the result never depends on discovery's map iteration order.
+fn order_entries(
+    entries: &mut [PathBuf],
+    order: PathOrder,
+    position: &HashMap<PathBuf, usize>,
+    rank: &HashMap<PathBuf, usize>,
+) {
+    let pos = |p: &PathBuf| position.get(p).copied().unwrap_or(usize::MAX);
+    let rank = |p: &PathBuf| rank.get(p).copied().unwrap_or(usize::MAX);
+    match order {
+        PathOrder::Preserve => entries.sort_by_cached_key(|p| (pos(p), rank(p), p.clone())),
+        PathOrder::Alphabetical => entries.sort(),
+        PathOrder::SourcePriority => entries.sort_by_cached_key(|p| (rank(p), pos(p), p.clone())),
+    }
+}
+
+/// Is this (lowercased) entry a Windows system directory? Those belong in the System PATH.
+fn is_windows_dir(norm: &str) -> bool {
+    norm.contains("\\windows\\") || norm.starts_with("c:\\windows")
+}
+
+/// Does the directory a PATH entry points at exist (after expanding `%VAR%` references)?
+fn directory_exists(system: &impl SystemOps, entry: &str) -> bool {
+    system.path_exists(&PathBuf::from(system.expand_env_vars(entry)))
+}
+
+/// The comparison key for a PATH entry: its canonical form (see `normalize::canonicalize`).
 ///
-/// - Lowercases the string (Windows is case-insensitive).
-fn normalize_path(p: &std::path::Path) -> PathBuf {
-    let s = p.to_string_lossy().to_string().to_lowercase();
-    PathBuf::from(s)
+/// Only ever used for comparing; the original spelling is what gets written back.
+fn entry_key(system: &impl SystemOps, entry: &str) -> PathBuf {
+    PathBuf::from(normalize::canonicalize(entry, |name| system.lookup_env_var(name)))
 }
 
 /// Converts a Windows path to POSIX format for Git Bash / MSYS2.
@@ -389,47 +812,61 @@ fn win_to_posix(path: &str) -> String {
     s
 }
 
-/// Applies the new PATH to the Windows Registry with transactional safety.
+/// Applies a new PATH of either scope to the Windows Registry with transactional safety.
 ///
 /// # Safety Steps
+/// 0.  **Length**: Refuses a value that would push the combined PATH past `budget::MAX_PATH_LENGTH`,
+///     because Windows would silently cut off its end.
 /// 1.  **Read Current**: Gets the existing PATH.
-/// 2.  **Backup**: Writes the existing PATH to `%LOCALAPPDATA%\wanderlust\backup.reg`.
-/// 3.  **Write**: Updates `HKCU\Environment\Path`.
+/// 2.  **Backup**: Adds the existing PATH to the history in `%LOCALAPPDATA%\wanderlust\backups` (see `backup`).
+/// 3.  **Write**: Updates `HKCU\Environment\Path` or `HKLM\...\Session Manager\Environment\Path`.
 /// 4.  **Broadcast**: Sends `WM_SETTINGCHANGE` so running apps (like Explorer) notice.
 /// 5.  **Verify**: Runs `cmd`, `powershell`, `whoami` to ensure the system is usable.
 /// 6.  **Rollback**: If verification fails, restores the old PATH and errors out.
-fn apply_path(system: &impl SystemOps, new_val: &str) -> Result<()> {
+///
+/// The System PATH is held to a stricter standard: if it can't be read or backed up,
+/// nothing is written to HKLM at all.
+///
+/// The value keeps (or is upgraded to) `REG_EXPAND_SZ` as needed, see `PathValue::replacing`.
+pub fn apply_path(system: &impl SystemOps, scope: Scope, new_val: &str) -> Result<()> {
+    let current = system.read_path_value(scope).ok().map(|v| v.kind);
+    apply_path_value(system, scope, &PathValue::replacing(current, new_val))
+}
+
+/// Like `apply_path`, but writes exactly `new_val`, registry type included (e.g. a restored backup).
+pub fn apply_path_value(system: &impl SystemOps, scope: Scope, new_val: &PathValue) -> Result<()> {
     // NOTE: Empty User PATH is VALID - it means all paths are in System PATH
     // This is actually the cleanest possible state
+
+    // 0. Refuse anything Windows would truncate
+    let length = match scope {
+        Scope::User => budget::measure(system, &system.read_system_path_registry().unwrap_or_default(), &new_val.data),
+        Scope::System => budget::measure(system, &new_val.data, &system.read_user_path_registry().unwrap_or_default()),
+    };
+    if length.is_truncated() {
+        bail!(
+            "Refusing to write the {:?} PATH: the combined PATH would be {} characters and Windows truncates it at {}. Try 'wanderlust heal --compact'.",
+            scope, length.combined, length.max
+        );
+    }
     
     // 1. Open Registry Key (Read Old)
-    let old_val = system.read_user_path_registry().unwrap_or_default();
+    // Keep the raw value and its type, so a rollback restores REG_EXPAND_SZ exactly.
+    let old_val = match scope {
+        Scope::User => system.read_path_value(Scope::User).unwrap_or_default(),
+        Scope::System => system.read_path_value(Scope::System)?,
+    };
 
-    // 2. Backup to %LOCALAPPDATA%\wanderlust\backup.reg
-    if let Some(base_dirs) = directories::BaseDirs::new() {
-        let app_data = base_dirs.data_local_dir().join("wanderlust");
-        
-        if let Err(e) = std::fs::create_dir_all(&app_data) {
-            warn!("Failed to create backup directory at {:?}: {}", app_data, e);
-        } else {
-            let backup_path = app_data.join("backup.reg");
-            // Escape backslashes for .reg file format ("\" -> "\\")
-            let escaped_old_val = old_val.replace("\\", "\\\\").replace("\"", "\\\"");
-            let reg_content = format!(
-                "Windows Registry Editor Version 5.00\n\n[HKEY_CURRENT_USER\\Environment]\n\"Path\"=\"{}\"\n",
-                escaped_old_val
-            );
-            
-            if let Err(e) = system.write_backup_file(&backup_path, &reg_content) {
-                error!("Failed to write backup content: {}", e);
-            } else {
-                 info!("Backed up old PATH to {:?}", backup_path);
-            }
+    // 2. Backup to %LOCALAPPDATA%\wanderlust\backups\<timestamp>-<scope>.reg
+    if let Err(e) = backup::create_backup(system, scope, &old_val) {
+        if scope == Scope::System {
+            bail!("Refusing to modify System PATH without a backup: {}", e);
         }
+        error!("Failed to write backup: {}", e);
     }
 
     // 3. Set new PATH
-    system.write_user_path_registry(new_val)?;
+    system.write_path_value(scope, new_val)?;
     
     // 4. Broadcast change (Twice with delay, to ensure standard apps pick it up)
     let _ = system.broadcast_environment_change();
@@ -442,15 +879,15 @@ fn apply_path(system: &impl SystemOps, new_val: &str) -> Result<()> {
 
     // 5. Verify consistency
     if !system.verify_environment_health() {
-        error!("Verification failed! The new PATH seems broken. Rolling back...");
+        error!("Verification failed! The new {:?} PATH seems broken. Rolling back...", scope);
         
         // ROLLBACK
-        if let Err(e) = system.write_user_path_registry(&old_val) {
-            error!("CRITICAL: Failed to write back old PATH: {}", e);
-            bail!("Verification failed AND Rollback failed. Please restore from backup manually.");
+        if let Err(e) = system.write_path_value(scope, &old_val) {
+            error!("CRITICAL: Failed to write back old {:?} PATH: {}", scope, e);
+            bail!("Verification failed AND Rollback failed. Please restore from backup manually ('wanderlust backups list').");
         }
         let _ = system.broadcast_environment_change();
-        bail!("Verification failed. Rolled back to previous PATH.");
+        bail!("Verification failed. Rolled back to previous {:?} PATH.", scope);
     }
     
     Ok(())
@@ -477,12 +914,13 @@ mod tests {
                 let p = if i < paths.len() { paths[i].clone() } else { "c:\temp".to_string() };
                 map.insert(cmd.clone(), vec![discovery::Candidate {
                     path: PathBuf::from(p),
-                    _source: "test".to_string()
+                    source: "test".to_string()
                 }]);
             }
 
             // Action
-            let result = build_minimal_path(&map);
+            let system = crate::system::MockSystem::new();
+            let result = build_minimal_path(&map, &system, &Config::default(), &HealState::default());
 
             // Assertions (Invariants are checked internal to the function, but we verify properties here)
             
@@ -518,12 +956,14 @@ mod tests {
             let mut map = HashMap::new();
             for (i, cmd) in cmd_names.iter().enumerate() {
                  let p = if i < paths.len() { paths[i].clone() } else { r"C:\Users\test\bin".to_string() };
-                 map.insert(cmd.clone(), vec![discovery::Candidate { path: PathBuf::from(p), _source: "test".to_string() }]);
+                 map.insert(cmd.clone(), vec![discovery::Candidate { path: PathBuf::from(p), source: "test".to_string() }]);
             }
             
             // Action
             // We force dry_run = false so it actually "writes" to the mock.
-            let result = run_healing(&map, &system, false);
+            // The starting entries don't exist; remove them on the first miss so the User PATH changes.
+            let config = Config { dead_entries: crate::config::DeadEntries { grace_runs: 1, ..Default::default() }, ..Config::default() };
+            let result = run_healing(&map, &system, &config, false);
             
             // Assertions
             prop_assert!(result.is_ok(), "Healing failed: {:?}", result.err());
@@ -536,4 +976,652 @@ mod tests {
             prop_assert!(broadcast, "Broadcast missed");
         }
     }
+
+    fn candidates(entries: &[(&str, &str)]) -> HashMap<String, Vec<discovery::Candidate>> {
+        let mut map: HashMap<String, Vec<discovery::Candidate>> = HashMap::new();
+        for (cmd, dir) in entries {
+            map.entry(cmd.to_string()).or_default().push(discovery::Candidate {
+                path: PathBuf::from(dir),
+                source: "test".to_string(),
+            });
+        }
+        map
+    }
+
+    /// A state in which the given canonical keys have been missing for long enough to be removed.
+    fn dead(keys: &[&str]) -> HealState {
+        let mut state = HealState::default();
+        for day in 0..crate::config::DeadEntries::default().grace_runs {
+            state.observe(keys.iter().map(|k| k.to_string()), u64::from(day) * crate::state::SECONDS_PER_DAY);
+        }
+        state
+    }
+
+    #[test]
+    fn test_build_minimal_path_honours_mock_system_path() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_system_path_registry(r"C:\Program Files\Git\cmd;C:\Program Files\nodejs").unwrap();
+        let map = candidates(&[
+            ("git", r"c:\program files\git\cmd"),
+            ("node", r"C:\Program Files\nodejs"),
+            ("cargo", r"C:\Users\test\.cargo\bin"),
+        ]);
+
+        let result = build_minimal_path(&map, &system, &Config::default(), &HealState::default());
+
+        assert_eq!(result, r"C:\Users\test\.cargo\bin");
+    }
+
+    #[test]
+    fn test_build_minimal_path_without_system_path() {
+        use crate::system::MockSystem;
+
+        // A mock with no System PATH must not fall back to the host registry.
+        let system = MockSystem::new();
+        let map = candidates(&[("node", r"C:\Program Files\nodejs")]);
+
+        assert_eq!(build_minimal_path(&map, &system, &Config::default(), &HealState::default()), r"C:\Program Files\nodejs");
+    }
+
+    #[test]
+    fn test_run_healing_dedupes_against_mock_system_path() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Tools;C:\Users\test\.cargo\bin").unwrap();
+        system.write_system_path_registry(r"C:\Tools").unwrap();
+        let map = candidates(&[
+            ("tool", r"C:\Tools"),
+            ("cargo", r"C:\Users\test\.cargo\bin"),
+        ]);
+
+        run_healing(&map, &system, &Config::default(), false).unwrap();
+
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\Users\test\.cargo\bin");
+        assert_eq!(system.read_system_path_registry().unwrap(), r"C:\Tools", "System PATH must be untouched");
+    }
+
+    #[test]
+    fn test_plan_healing_reasons_without_side_effects() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        let user_before = r"C:\Tools;C:\Gone;C:\Users\test\docs;c:\users\test\docs";
+        let system_before = r"C:\Tools;C:\Windows;c:\windows";
+        system.write_user_path_registry(user_before).unwrap();
+        system.write_system_path_registry(system_before).unwrap();
+        system.add_dir(r"C:\Users\test\docs");
+        let map = candidates(&[("tool", r"C:\Tools")]);
+        let mut map_with_scoop = map.clone();
+        map_with_scoop.insert("rg".to_string(), vec![discovery::Candidate {
+            path: PathBuf::from(r"C:\Users\test\scoop\shims"),
+            source: "scoop".to_string(),
+        }]);
+
+        let plan = plan_healing(&map_with_scoop, &system, &Config::default(), &dead(&[r"c:\gone"]));
+
+        let reasons: Vec<(Scope, Action, &str, String)> = plan.changes.iter()
+            .map(|c| (c.scope, c.action, c.path.as_str(), c.reason.to_string()))
+            .collect();
+        assert_eq!(reasons, vec![
+            (Scope::User, Action::Remove, r"C:\Tools", "duplicate of system".to_string()),
+            (Scope::User, Action::Remove, r"C:\Gone", "missing directory".to_string()),
+            (Scope::User, Action::Remove, r"c:\users\test\docs", "duplicate".to_string()),
+            (Scope::User, Action::Add, r"C:\Users\test\scoop\shims", "discovered via scoop".to_string()),
+            (Scope::System, Action::Remove, r"c:\windows", "duplicate".to_string()),
+        ]);
+        assert_eq!(plan.system_path_after, r"C:\Tools;C:\Windows");
+
+        // Planning must not write anything
+        assert_eq!(system.read_user_path_registry().unwrap(), user_before);
+        assert_eq!(system.read_system_path_registry().unwrap(), system_before);
+        assert!(!*system.broadcast_called.lock().unwrap());
+    }
+
+    #[test]
+    fn test_apply_plan_writes_planned_values() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Old").unwrap();
+        system.write_system_path_registry(r"C:\Sys;C:\Sys").unwrap();
+        let plan = plan_healing(&candidates(&[("new", r"C:\New")]), &system, &Config::default(), &dead(&[r"c:\old"]));
+
+        apply_plan(&system, &plan).unwrap();
+
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\New");
+        assert_eq!(system.read_system_path_registry().unwrap(), r"C:\Sys");
+        assert!(*system.broadcast_called.lock().unwrap());
+    }
+
+    #[test]
+    fn test_apply_noop_plan_does_not_rewrite_user_path() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"c:\new").unwrap();
+        let plan = plan_healing(&candidates(&[("new", r"C:\New")]), &system, &Config::default(), &HealState::default());
+        assert!(plan.is_noop());
+
+        apply_plan(&system, &plan).unwrap();
+
+        assert!(!*system.broadcast_called.lock().unwrap());
+    }
+
+    #[test]
+    fn test_apply_plan_refuses_stale_user_path() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Old").unwrap();
+        let plan = plan_healing(&candidates(&[("new", r"C:\New")]), &system, &Config::default(), &HealState::default());
+        system.write_user_path_registry(r"C:\Old;C:\Installer\bin").unwrap();
+
+        let err = apply_plan(&system, &plan).unwrap_err();
+
+        assert!(err.to_string().contains("User PATH has changed"), "{}", err);
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\Old;C:\Installer\bin");
+        assert!(!*system.broadcast_called.lock().unwrap());
+    }
+
+    #[test]
+    fn test_apply_plan_refuses_stale_system_path() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Old").unwrap();
+        system.write_system_path_registry(r"C:\Sys").unwrap();
+        let plan = plan_healing(&candidates(&[("new", r"C:\New")]), &system, &Config::default(), &HealState::default());
+        system.write_system_path_registry(r"C:\Sys;C:\New").unwrap();
+
+        let err = apply_plan(&system, &plan).unwrap_err();
+
+        assert!(err.to_string().contains("System PATH has changed"), "{}", err);
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\Old");
+    }
+
+    #[test]
+    fn test_run_healing_keeps_expand_sz_references_unexpanded() {
+        use crate::system::{MockSystem, PathValue, ValueKind};
+
+        let system = MockSystem::new();
+        system.set_env("USERPROFILE", r"C:\Users\test");
+        system.write_path_value(Scope::User, &PathValue {
+            data: r"%USERPROFILE%\.cargo\bin;C:\Users\test\.cargo\bin;C:\Stale".to_string(),
+            kind: ValueKind::ExpandString,
+        }).unwrap();
+        system.write_system_path_registry(r"C:\Windows\system32").unwrap();
+        let map = candidates(&[("cargo", r"C:\Users\test\.cargo\bin")]);
+
+        let plan = plan_healing(&map, &system, &Config::default(), &dead(&[r"c:\stale"]));
+        apply_plan(&system, &plan).unwrap();
+
+        let written = system.read_path_value(Scope::User).unwrap();
+        assert_eq!(written.data, r"%USERPROFILE%\.cargo\bin");
+        assert_eq!(written.kind, ValueKind::ExpandString, "REG_EXPAND_SZ must not be downgraded to REG_SZ");
+        let removed: Vec<(&str, String)> = plan.changes_for(Scope::User, Action::Remove)
+            .map(|c| (c.path.as_str(), c.reason.to_string()))
+            .collect();
+        assert_eq!(removed[0], (r"C:\Users\test\.cargo\bin", "duplicate".to_string()), "Compared in expanded form");
+        assert_eq!(plan.changes_for(Scope::User, Action::Add).count(), 0);
+    }
+
+    #[test]
+    fn test_clean_system_entries_compares_expanded_forms() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.set_env("SystemRoot", r"C:\Windows");
+        let (cleaned, _) = clean_system_entries(&system, r"%SystemRoot%\system32;C:\Windows\System32;%SystemRoot%", |_| false);
+
+        assert_eq!(cleaned, r"%SystemRoot%\system32;%SystemRoot%");
+    }
+
+    #[test]
+    fn test_clean_system_entries_compares_canonical_forms() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        let (cleaned, _) = clean_system_entries(&system, r#"C:\Tools\;"c:\tools";C:/Tools/./;\\?\C:\Tools;\\Server\Share\;\\server\share"#, |_| false);
+
+        assert_eq!(cleaned, r"C:\Tools\;\\Server\Share\");
+    }
+
+    #[test]
+    fn test_build_minimal_path_order_strategies() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Zeta;C:\Alpha").unwrap();
+        let mut map = candidates(&[("zeta", r"C:\Zeta"), ("alpha", r"C:\Alpha"), ("beta", r"C:\Beta"), ("aardvark", r"C:\Aardvark")]);
+        map.get_mut("beta").unwrap()[0].source = "cargo".to_string();
+        map.get_mut("alpha").unwrap()[0].source = "scoop".to_string();
+
+        let with_order = |order| build_minimal_path(&map, &system, &Config { order, ..Config::default() }, &HealState::default());
+
+        assert_eq!(with_order(PathOrder::Preserve), r"C:\Zeta;C:\Alpha;C:\Beta;C:\Aardvark", "New entries best source first");
+        assert_eq!(with_order(PathOrder::Alphabetical), r"C:\Aardvark;C:\Alpha;C:\Beta;C:\Zeta");
+        assert_eq!(with_order(PathOrder::SourcePriority), r"C:\Alpha;C:\Beta;C:\Zeta;C:\Aardvark");
+    }
+
+    #[test]
+    fn test_plan_healing_ranks_entries_by_pin_and_source() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Scripts").unwrap();
+        system.add_dir(r"C:\Scripts");
+        let mut map = candidates(&[("python", r"C:\Python312"), ("python", r"C:\Users\me\scoop\shims"), ("tool", r"D:\corp\bin")]);
+        map.get_mut("python").unwrap()[0].source = "HKLM_Uninstall".to_string();
+        map.get_mut("python").unwrap()[1].source = "scoop".to_string();
+        let (config, _) = Config::from_toml(r#"
+            order = "source-priority"
+            pin = ['D:\corp\bin']
+            source_priority = ["scoop", "HKLM_Uninstall"]
+        "#).unwrap();
+
+        let plan = plan_healing(&map, &system, &config, &HealState::default());
+
+        let order: Vec<(&str, String)> = plan.user_order.iter().map(|e| (e.path.as_str(), e.rank.to_string())).collect();
+        assert_eq!(order, vec![
+            (r"D:\corp\bin", "pin #1".to_string()),
+            (r"C:\Users\me\scoop\shims", "scoop, rank 1".to_string()),
+            (r"C:\Python312", "HKLM_Uninstall, rank 2".to_string()),
+            (r"C:\Scripts", "unranked, kept from the current PATH".to_string()),
+        ], "The scoop copy of python wins over the Uninstall one");
+    }
+
+    #[test]
+    fn test_build_minimal_path_applies_pin_and_block_rules() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Tools;C:\Users\me\scoop\shims").unwrap();
+        let map = candidates(&[
+            ("tool", r"C:\Tools"),
+            ("rg", r"C:\Users\me\scoop\shims"),
+            ("java", r"C:\Program Files\Oracle\Java\javapath"),
+        ]);
+        let (config, _) = Config::from_toml(r#"
+            pin = ['D:\corp\bin', 'C:\Users\*\scoop\shims']
+            block = ['C:\Program Files\Oracle\**']
+        "#).unwrap();
+
+        assert_eq!(build_minimal_path(&map, &system, &config, &HealState::default()), r"D:\corp\bin;C:\Users\me\scoop\shims;C:\Tools");
+    }
+
+    #[test]
+    fn test_build_minimal_path_writes_new_entries_as_spelled() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.set_env("USERPROFILE", r"C:\Users\X");
+        let map = candidates(&[("node", r"C:\Program Files\nodejs"), ("tool", r"C:\Users\X\Tools")]);
+        let (config, _) = Config::from_toml(r#"pin = ['%USERPROFILE%\Tools']"#).unwrap();
+
+        assert_eq!(
+            build_minimal_path(&map, &system, &config, &HealState::default()),
+            r"%USERPROFILE%\Tools;C:\Program Files\nodejs",
+            "Canonical keys are only for comparing",
+        );
+    }
+
+    #[test]
+    fn test_plan_healing_honours_scopes_and_explains_rules() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Oracle\bin;C:\Tools").unwrap();
+        system.write_system_path_registry(r"C:\Windows;C:\Windows").unwrap();
+        let map = candidates(&[("tool", r"C:\Tools"), ("java", r"C:\Oracle\bin")]);
+        let (config, _) = Config::from_toml(r#"
+            pin = ['D:\corp\bin']
+            block = ['C:\Oracle\**']
+            [scopes]
+            system = false
+        "#).unwrap();
+
+        let plan = plan_healing(&map, &system, &config, &HealState::default());
+
+        assert!(!plan.system_changed(), "System scope is switched off");
+        assert_eq!(plan.user_path_after, r"D:\corp\bin;C:\Tools");
+        let reasons: Vec<(Action, Reason)> = plan.changes.iter().map(|c| (c.action, c.reason.clone())).collect();
+        assert_eq!(reasons, vec![(Action::Remove, Reason::Blocked), (Action::Add, Reason::Pinned)]);
+
+        let user_off = Config { scopes: crate::config::Scopes { user: false, system: true }, ..config };
+        let plan = plan_healing(&map, &system, &user_off, &HealState::default());
+        assert!(!plan.user_changed());
+        assert!(plan.changes.iter().all(|c| c.scope == Scope::System));
+    }
+
+    #[test]
+    fn test_build_minimal_path_skips_system_entries_spelled_differently() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_system_path_registry(r"C:\Tools\;C:\Program Files\Git\cmd").unwrap();
+        let map = candidates(&[("tool", r"c:/tools"), ("git", r"C:\PROGRAM FILES\Git\cmd\")]);
+
+        assert_eq!(build_minimal_path(&map, &system, &Config::default(), &HealState::default()), "");
+    }
+
+    #[test]
+    fn test_build_minimal_path_keeps_existing_entries_without_executables() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"C:\Tools\dlls;C:\Users\me\Scripts;C:\Windows\System32\OpenSSH;c:\tools\DLLS").unwrap();
+        system.add_file(r"C:\Tools\dlls\libcrypto-3.dll");
+        system.add_file(r"C:\Users\me\Scripts\deploy.ps1");
+        system.add_dir(r"C:\Windows\System32\OpenSSH");
+        let map = candidates(&[("cargo", r"C:\Users\me\.cargo\bin")]);
+
+        let plan = plan_healing(&map, &system, &Config::default(), &HealState::default());
+
+        assert_eq!(plan.user_path_after, r"C:\Tools\dlls;C:\Users\me\Scripts;C:\Windows\System32\OpenSSH;C:\Users\me\.cargo\bin");
+        let removed: Vec<(&str, Reason)> = plan.changes_for(Scope::User, Action::Remove).map(|c| (c.path.as_str(), c.reason.clone())).collect();
+        assert_eq!(removed, vec![(r"c:\tools\DLLS", Reason::Duplicate)]);
+    }
+
+    #[test]
+    fn test_run_healing_removes_missing_entries_after_grace_runs() {
+        use crate::config::DeadEntries;
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"E:\Tools;C:\Users\test\.cargo\bin").unwrap();
+        system.write_system_path_registry(r"\\vpn\share\bin;C:\Windows").unwrap();
+        system.add_dir(r"C:\Users\test\.cargo\bin");
+        system.add_dir(r"C:\Windows");
+        let map = candidates(&[("cargo", r"C:\Users\test\.cargo\bin")]);
+        let config = Config { dead_entries: DeadEntries { grace_runs: 2, grace_days: 30 }, ..Config::default() };
+
+        run_healing(&map, &system, &config, false).unwrap();
+        run_healing(&map, &system, &config, true).unwrap();
+        assert_eq!(system.read_user_path_registry().unwrap(), r"E:\Tools;C:\Users\test\.cargo\bin", "Kept on the first miss");
+        assert_eq!(system.read_system_path_registry().unwrap(), r"\\vpn\share\bin;C:\Windows");
+        assert_eq!(HealState::load(&system).missing[r"e:\tools"].runs, 1, "Dry runs don't count");
+
+        let plan = plan_healing(&map, &system, &config, &dead(&[r"e:\tools", r"\\vpn\share\bin"]));
+        let removed: Vec<(Scope, Reason)> = plan.changes.iter().map(|c| (c.scope, c.reason.clone())).collect();
+        assert_eq!(removed, vec![(Scope::User, Reason::MissingDirectory), (Scope::System, Reason::MissingDirectory)]);
+
+        // Heals later the same day don't count; pretend the first one was yesterday
+        run_healing(&map, &system, &config, false).unwrap();
+        assert_eq!(system.read_user_path_registry().unwrap(), r"E:\Tools;C:\Users\test\.cargo\bin");
+        let mut state = HealState::load(&system);
+        for entry in state.missing.values_mut() {
+            entry.first_missing -= crate::state::SECONDS_PER_DAY;
+            entry.last_missing -= crate::state::SECONDS_PER_DAY;
+        }
+        state.save(&system).unwrap();
+
+        run_healing(&map, &system, &config, false).unwrap();
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\Users\test\.cargo\bin");
+        assert_eq!(system.read_system_path_registry().unwrap(), r"C:\Windows");
+    }
+
+    #[test]
+    fn test_export_plan_does_not_count_missing_entries() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_user_path_registry(r"E:\Tools").unwrap();
+        system.write_system_path_registry(r"C:\Windows").unwrap();
+        let plan_file = std::env::temp_dir().join(format!("wanderlust-test-plan-{}.json", std::process::id()));
+
+        export_plan(&HashMap::new(), &system, &Config::default(), &plan_file).unwrap();
+        export_plan(&HashMap::new(), &system, &Config::default(), &plan_file).unwrap();
+
+        let plan = HealPlan::load(&plan_file).unwrap();
+        let _ = std::fs::remove_file(&plan_file);
+        assert_eq!(plan.user_path_after, r"E:\Tools");
+        assert!(HealState::load(&system).missing.is_empty(), "Only applied heals count");
+    }
+
+    #[test]
+    fn test_plan_healing_compacts_to_fit_the_length_budget() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.set_env("LOCALAPPDATA", r"C:\Users\someone\AppData\Local");
+        let long_entry = format!(r"D:\{}", "x".repeat(200));
+        system.write_system_path_registry(&format!(r"C:\{}", "s".repeat(1900))).unwrap();
+        system.write_user_path_registry(&format!(r"C:\Users\someone\AppData\Local\Programs\alpha;D:\corp\bin;{}", long_entry)).unwrap();
+        let (mut config, _) = Config::from_toml(r#"pin = ['D:\corp\bin']"#).unwrap();
+
+        let plan = plan_healing(&HashMap::new(), &system, &config, &HealState::default());
+        assert!(plan.length_after.combined > budget::LEGACY_PATH_LIMIT);
+        assert!(plan.changes.is_empty(), "Compaction is opt-in");
+
+        config.compact = true;
+        let plan = plan_healing(&HashMap::new(), &system, &config, &HealState::default());
+
+        assert_eq!(plan.user_path_after, r"D:\corp\bin;%LOCALAPPDATA%\Programs\alpha");
+        assert!(plan.length_after.fits());
+        let removed: Vec<Reason> = plan.changes_for(Scope::User, Action::Remove).map(|c| c.reason.clone()).collect();
+        assert_eq!(removed, vec![
+            Reason::Shortened { to: r"%LOCALAPPDATA%\Programs\alpha".to_string() },
+            Reason::OverLengthBudget,
+        ]);
+    }
+
+    #[test]
+    fn test_apply_path_refuses_values_windows_would_truncate() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_system_path_registry(&"s".repeat(30_000)).unwrap();
+        system.write_user_path_registry(r"C:\Old").unwrap();
+
+        let err = apply_path(&system, Scope::User, &"u".repeat(3_000)).unwrap_err();
+
+        assert!(err.to_string().contains("Refusing"), "{}", err);
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\Old");
+        assert!(backup::list_backups(&system).is_empty());
+        assert!(apply_path(&system, Scope::User, r"C:\New").is_ok());
+    }
+
+    #[test]
+    fn test_plan_healing_rebalances_scopes() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.set_env("USERPROFILE", r"C:\Users\alice");
+        system.set_env("ProgramFiles", r"C:\Program Files");
+        system.write_system_path_registry(r"C:\Program Files\Git\cmd;C:\Users\alice\AppData\Local\Tool").unwrap();
+        system.write_user_path_registry(r"C:\Program Files\Java\bin;C:\Users\alice\.cargo\bin").unwrap();
+        let map = candidates(&[("cargo", r"C:\Users\alice\.cargo\bin")]);
+        let config = Config { rebalance: true, ..Config::default() };
+
+        let plan = plan_healing(&map, &system, &config, &HealState::default());
+
+        assert_eq!(plan.system_path_after, r"C:\Program Files\Git\cmd;C:\Program Files\Java\bin");
+        assert_eq!(plan.user_path_after, r"C:\Users\alice\AppData\Local\Tool;C:\Users\alice\.cargo\bin");
+        let changes: Vec<(Scope, Action, &str, String)> = plan.changes.iter()
+            .map(|c| (c.scope, c.action, c.path.as_str(), c.reason.to_string()))
+            .collect();
+        assert_eq!(changes, vec![
+            (Scope::User, Action::Remove, r"C:\Program Files\Java\bin", "moved from User to System PATH".to_string()),
+            (Scope::User, Action::Add, r"C:\Users\alice\AppData\Local\Tool", "moved from System to User PATH".to_string()),
+            (Scope::System, Action::Remove, r"C:\Users\alice\AppData\Local\Tool", "moved from System to User PATH".to_string()),
+            (Scope::System, Action::Add, r"C:\Program Files\Java\bin", "moved from User to System PATH".to_string()),
+        ]);
+
+        let no_system = Config { scopes: crate::config::Scopes { user: true, system: false }, ..config };
+        assert!(plan_healing(&map, &system, &no_system, &HealState::default()).changes.is_empty());
+    }
+
+    #[test]
+    fn test_apply_plan_keeps_user_path_when_entries_cannot_move_to_system() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.set_env("ProgramFiles", r"C:\Program Files");
+        system.write_system_path_registry(r"C:\Windows").unwrap();
+        system.write_user_path_registry(r"C:\Program Files\Java\bin").unwrap();
+        let plan = plan_healing(&HashMap::new(), &system, &Config { rebalance: true, ..Config::default() }, &HealState::default());
+        assert_eq!(plan.user_path_after, "");
+        // The System PATH write fails verification and is rolled back
+        *system.unhealthy.lock().unwrap() = true;
+
+        assert!(apply_plan(&system, &plan).is_err());
+        assert_eq!(system.read_system_path_registry().unwrap(), r"C:\Windows");
+        assert_eq!(system.read_user_path_registry().unwrap(), r"C:\Program Files\Java\bin", "Must not lose the entry");
+    }
+
+    #[test]
+    fn test_plan_healing_puts_chosen_jdk_ahead_of_javapath() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        let javapath = r"C:\Program Files\Common Files\Oracle\Java\javapath";
+        let jdk = r"C:\Program Files\Eclipse Adoptium\jdk-21\bin";
+        system.write_system_path_registry(&format!(r"C:\Windows;{};C:\Tools", javapath)).unwrap();
+        system.write_user_path_registry(jdk).unwrap();
+        let mut map = candidates(&[]);
+        map.insert("java".to_string(), vec![discovery::Candidate { path: PathBuf::from(jdk), source: java::SOURCE.to_string() }]);
+
+        let plan = plan_healing(&map, &system, &Config::default(), &HealState::default());
+
+        assert_eq!(plan.system_path_after, format!(r"C:\Windows;{};{};C:\Tools", jdk, javapath));
+        assert_eq!(plan.user_path_after, jdk, "Kept until the System PATH has it");
+        let changes: Vec<(Scope, Action, String)> = plan.changes.iter()
+            .map(|c| (c.scope, c.action, c.reason.to_string()))
+            .collect();
+        assert_eq!(changes, vec![
+            (Scope::System, Action::Add, "chosen JDK, ahead of Oracle's javapath".to_string()),
+        ]);
+
+        // Once it is ahead, the User PATH copy is a plain duplicate
+        apply_plan(&system, &plan).unwrap();
+        let plan = plan_healing(&map, &system, &Config::default(), &HealState::default());
+        assert!(!plan.system_changed());
+        assert_eq!(plan.user_path_after, "");
+    }
+
+    #[test]
+    fn test_apply_plan_without_administrator_still_heals_user_path_around_javapath() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        let javapath = r"C:\Program Files\Common Files\Oracle\Java\javapath";
+        let jdk = r"C:\Program Files\Eclipse Adoptium\jdk-21\bin";
+        system.write_system_path_registry(&format!(r"C:\Windows;{}", javapath)).unwrap();
+        system.write_user_path_registry(&format!(r"{};{}", jdk, jdk)).unwrap();
+        let mut map = candidates(&[]);
+        map.insert("java".to_string(), vec![discovery::Candidate { path: PathBuf::from(jdk), source: java::SOURCE.to_string() }]);
+        let plan = plan_healing(&map, &system, &Config::default(), &HealState::default());
+        assert!(plan.system_changed());
+        *system.system_read_only.lock().unwrap() = true;
+
+        apply_plan(&system, &plan).unwrap();
+
+        assert_eq!(system.read_system_path_registry().unwrap(), format!(r"C:\Windows;{}", javapath));
+        assert_eq!(system.read_user_path_registry().unwrap(), jdk, "The User PATH is healed anyway, JDK included");
+    }
+
+    #[test]
+    fn test_apply_plan_sets_java_home() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        let jdk = r"C:\Program Files\Microsoft\jdk-17.0.10.7-hotspot\bin";
+        system.write_system_path_registry(r"C:\Windows").unwrap();
+        system.write_user_path_registry(jdk).unwrap();
+        system.write_user_env_var("JAVA_HOME", r"C:\Program Files\Java\jdk1.8.0_401").unwrap();
+        let mut map = candidates(&[]);
+        map.insert("java".to_string(), vec![discovery::Candidate { path: PathBuf::from(jdk), source: java::SOURCE.to_string() }]);
+
+        assert!(plan_healing(&map, &system, &Config::default(), &HealState::default()).is_noop(), "Opt-in");
+
+        let mut config = Config::default();
+        config.java.set_java_home = true;
+        let plan = plan_healing(&map, &system, &config, &HealState::default());
+        assert_eq!(plan.java_home_after.as_deref(), Some(r"C:\Program Files\Microsoft\jdk-17.0.10.7-hotspot"));
+
+        system.write_user_env_var("JAVA_HOME", r"C:\elsewhere").unwrap();
+        assert!(apply_plan(&system, &plan).is_err(), "JAVA_HOME changed since planning");
+        system.write_user_env_var("JAVA_HOME", r"C:\Program Files\Java\jdk1.8.0_401").unwrap();
+
+        apply_plan(&system, &plan).unwrap();
+        assert_eq!(system.read_user_env_var("java_home").as_deref(), Some(r"C:\Program Files\Microsoft\jdk-17.0.10.7-hotspot"));
+        assert!(*system.broadcast_called.lock().unwrap());
+    }
+
+    #[test]
+    fn test_path_value_replacing_keeps_expand_sz() {
+        use crate::system::{PathValue, ValueKind};
+
+        assert_eq!(PathValue::replacing(Some(ValueKind::ExpandString), r"C:\a").kind, ValueKind::ExpandString);
+        assert_eq!(PathValue::replacing(Some(ValueKind::String), r"C:\a").kind, ValueKind::String);
+        assert_eq!(PathValue::replacing(Some(ValueKind::String), r"%USERPROFILE%\a").kind, ValueKind::ExpandString);
+        assert_eq!(PathValue::replacing(None, r"C:\a").kind, ValueKind::ExpandString);
+    }
+
+    #[test]
+    fn test_win_to_posix() {
+        assert_eq!(win_to_posix(r"C:\Windows\system32"), "/c/Windows/system32");
+        assert_eq!(win_to_posix(r"D:\Program Files\Git"), "/d/Program Files/Git");
+        assert_eq!(win_to_posix(r"relative\dir"), "relative/dir");
+    }
+
+    #[test]
+    fn test_apply_plan_removes_system_duplicates() {
+        use crate::system::MockSystem;
+
+        let system = MockSystem::new();
+        system.write_system_path_registry(r"C:\Windows\system32;C:\Tools;c:\windows\SYSTEM32;;C:\Tools").unwrap();
+        let plan = plan_healing(&HashMap::new(), &system, &Config::default(), &HealState::default());
+
+        apply_plan(&system, &plan).unwrap();
+
+        assert_eq!(system.read_system_path_registry().unwrap(), r"C:\Windows\system32;C:\Tools");
+    }
+
+    #[test]
+    fn test_apply_path_backs_up_hklm() {
+        use crate::system::MockSystem;
+
+        let original = r"C:\Tools;C:\Tools";
+        let system = MockSystem::new();
+        system.write_system_path_registry(original).unwrap();
+
+        apply_path(&system, Scope::System, r"C:\Tools").unwrap();
+
+        let backups = backup::list_backups(&system);
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].scope, Scope::System);
+        assert_eq!(backups[0].value, original);
+    }
+
+    #[test]
+    fn test_apply_path_rolls_back_hklm_on_failed_verification() {
+        use crate::system::MockSystem;
+
+        let original = r"C:\Tools;C:\Tools";
+        let system = MockSystem::new();
+        system.write_system_path_registry(original).unwrap();
+        *system.unhealthy.lock().unwrap() = true;
+
+        let err = apply_path(&system, Scope::System, r"C:\Tools").unwrap_err();
+
+        assert!(err.to_string().contains("Rolled back"), "{}", err);
+        assert_eq!(system.read_system_path_registry().unwrap(), original);
+    }
+
+    #[test]
+    fn test_dry_run_leaves_system_path_alone() {
+        use crate::system::MockSystem;
+
+        let original = r"C:\Tools;C:\Tools";
+        let system = MockSystem::new();
+        system.write_system_path_registry(original).unwrap();
+
+        run_healing(&HashMap::new(), &system, &Config::default(), true).unwrap();
+
+        assert_eq!(system.read_system_path_registry().unwrap(), original);
+    }
 }
@@ -0,0 +1,536 @@
+//! # Configuration
+//!
+//! Settings that shape the healed User PATH, read from `%APPDATA%\wanderlust\config.toml`.
+//! Every setting has a default that matches Wanderlust's out-of-the-box behavior, so a
+//! missing or empty config changes nothing.
+//!
+//! ```toml
+//! order = "preserve"            # preserve | alphabetical | source-priority
+//! source_priority = ["scoop", "cargo", "python"]   # best first; also orders new entries under preserve
+//! pin = ['D:\corp\bin']         # always kept, always first, in this order
+//! block = ['C:\Program Files\Oracle\**']   # never added, removed if present
+//! allow = ['C:\Program Files\Oracle\jdk-21\bin']   # exceptions to `block`
+//! compact = true                # shorten the User PATH if it is over a length limit
+//! rebalance = true              # move entries to the scope they belong in
+//!
+//! [scopes]
+//! system = false                # never touch the System PATH
+//!
+//! [sources]
+//! HKLM_Uninstall = false        # skip a discovery source
+//!
+//! [dead_entries]
+//! grace_runs = 3                # a missing directory is removed once heals missed it on 3 days...
+//! grace_days = 7                # ...or 7 days after the first miss, whichever comes first
+//!
+//! [python]
+//! default = "3.12"              # auto | newest | pyenv | conda | none | a version
+//!
+//! [java]
+//! default = "21"                # auto | newest | none | a major version
+//! set_java_home = true          # point the User JAVA_HOME at the chosen JDK
+//!
+//! [[tool_homes]]                # more directories to discover, see `tool_homes`
+//! name = "corp"
+//! path = '%USERPROFILE%\corp\bin'
+//! ```
+//!
+//! Rules are globs matched against the canonical form of an entry (see `normalize::canonicalize`),
+//! so they are case-insensitive, accept `\` or `/`, and may use `%VAR%` references.
+//! `*` matches within one directory name, `**` across any number of them.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use globset::{GlobBuilder, GlobMatcher};
+use log::warn;
+use serde::Deserialize;
+use crate::normalize;
+use crate::tool_homes::ToolHome;
+
+/// Every discovery source label, in the default `source_priority` order.
+pub const KNOWN_SOURCES: &[&str] = &[
+    "scoop",
+    "cargo",
+    "go",
+    "dotnet_tools",
+    "deno",
+    "bun",
+    "npm",
+    "pnpm",
+    "yarn",
+    "ghcup",
+    "cabal",
+    "stack",
+    "juliaup",
+    "composer",
+    "dart_pub",
+    "nimble",
+    "mise",
+    "winget",
+    "chocolatey",
+    "chocolatey_tools",
+    "nvm",
+    "fnm",
+    "volta",
+    "local_bin",
+    "python",
+    "python_scripts",
+    "python_system",
+    "py_launcher",
+    "pyenv",
+    "conda",
+    "python_tools",
+    "jdk",
+    "HKCU_Uninstall",
+    "HKLM_Uninstall",
+    "existing_path",
+];
+
+/// How entries are ordered in the rebuilt User PATH.
+///
+/// Order is semantics on Windows: the first directory containing `node.exe` wins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathOrder {
+    /// Existing entries keep their relative order; new ones are appended by their best-ranked
+    /// discovery source (see `Config::source_priority`), then alphabetically.
+    #[default]
+    Preserve,
+    /// Everything sorted alphabetically, ignoring the current order.
+    Alphabetical,
+    /// Ordered by the best-ranked discovery source (see `Config::source_priority`).
+    SourcePriority,
+}
+
+/// Which PATH variables Wanderlust may modify.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Scopes {
+    /// Rebuild the User PATH (HKCU).
+    pub user: bool,
+    /// Deduplicate the System PATH (HKLM).
+    pub system: bool,
+}
+
+impl Default for Scopes {
+    fn default() -> Self {
+        Self { user: true, system: true }
+    }
+}
+
+/// When an entry whose directory is missing counts as dead and gets removed.
+///
+/// Removable drives and VPN shares disappear temporarily, so one miss is not enough:
+/// an entry is removed once heals have found it missing on `grace_runs` different days
+/// without finding it in between, or `grace_days` after the first miss, whichever comes
+/// first (see `state::HealState`). Heals on the same day count once, so the scheduled
+/// heal every 30 minutes does not use up the grace in an afternoon.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct DeadEntries {
+    /// Days (UTC) a heal must have found an entry missing on. `1` removes it on the first miss.
+    pub grace_runs: u32,
+    /// Days an entry must have been missing for.
+    pub grace_days: u64,
+}
+
+impl Default for DeadEntries {
+    fn default() -> Self {
+        Self { grace_runs: 3, grace_days: 7 }
+    }
+}
+
+/// Which Python interpreter goes on the PATH (see `python`). Only one ever does.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum PythonDefault {
+    /// pyenv's shims if pyenv-win is installed, otherwise the newest interpreter.
+    #[default]
+    Auto,
+    /// The newest installed interpreter.
+    Newest,
+    /// pyenv-win's shims.
+    Pyenv,
+    /// The conda base environment.
+    Conda,
+    /// No interpreter; use the `py` launcher.
+    None,
+    /// The newest interpreter of this version, e.g. `3.11`.
+    Version(String),
+}
+
+impl TryFrom<String> for PythonDefault {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "newest" => Ok(Self::Newest),
+            "pyenv" => Ok(Self::Pyenv),
+            "conda" => Ok(Self::Conda),
+            "none" => Ok(Self::None),
+            version if !version.is_empty() && version.split('.').all(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())) => {
+                Ok(Self::Version(value))
+            }
+            _ => Err(format!("'{}' is not auto, newest, pyenv, conda, none or a version like 3.12", value)),
+        }
+    }
+}
+
+/// Python discovery settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PythonSettings {
+    /// Which interpreter to put on the PATH.
+    pub default: PythonDefault,
+}
+
+/// Which JDK goes on the PATH (see `java`). Only one ever does.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum JavaDefault {
+    /// The JDK `JAVA_HOME` points at, otherwise the newest.
+    #[default]
+    Auto,
+    /// The newest installed JDK.
+    Newest,
+    /// No JDK; leave Java to whatever is on the PATH already.
+    None,
+    /// The newest JDK of this major version, e.g. `17` (`8` for `1.8`).
+    Version(u32),
+}
+
+impl TryFrom<String> for JavaDefault {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "newest" => Ok(Self::Newest),
+            "none" => Ok(Self::None),
+            "1.8" => Ok(Self::Version(8)),
+            version => version.parse().map(Self::Version)
+                .map_err(|_| format!("'{}' is not auto, newest, none or a major version like 21", value)),
+        }
+    }
+}
+
+/// JDK discovery settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct JavaSettings {
+    /// Which JDK to put on the PATH.
+    pub default: JavaDefault,
+    /// Also point the User `JAVA_HOME` at it.
+    pub set_java_home: bool,
+}
+
+/// Wanderlust settings.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// How to order the rebuilt User PATH.
+    pub order: PathOrder,
+    /// Discovery sources from most to least preferred, used by `PathOrder::SourcePriority` and
+    /// for new entries under `PathOrder::Preserve`. Sources not listed rank after all listed ones.
+    pub source_priority: Vec<String>,
+    /// Entries that are always kept and placed first, in rule order. A pin without
+    /// wildcards is added even if discovery never finds it.
+    pub pin: Vec<String>,
+    /// Entries that are never added, and are removed from the User PATH.
+    pub block: Vec<String>,
+    /// Exceptions to `block`.
+    pub allow: Vec<String>,
+    /// Shorten the User PATH when the combined PATH is over a length limit (see `budget`):
+    /// first with `%VAR%`-relative forms, then by dropping its last, unpinned entries.
+    pub compact: bool,
+    /// Move per-user entries out of the System PATH and machine-wide ones out of the User PATH
+    /// (see `rebalance`). Needs both scopes.
+    pub rebalance: bool,
+    /// Which PATH variables may be modified.
+    pub scopes: Scopes,
+    /// Discovery sources switched on or off by label (all are on by default).
+    pub sources: BTreeMap<String, bool>,
+    /// How long missing directories are kept before being removed.
+    pub dead_entries: DeadEntries,
+    /// How Python interpreters are discovered.
+    pub python: PythonSettings,
+    /// How JDKs are discovered, and whether `JAVA_HOME` follows.
+    pub java: JavaSettings,
+    /// Directories to discover in addition to `tool_homes::DEFAULT_TOOL_HOMES`.
+    pub tool_homes: Vec<ToolHome>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            order: PathOrder::default(),
+            source_priority: KNOWN_SOURCES.iter().map(|s| s.to_string()).collect(),
+            pin: Vec::new(),
+            block: Vec::new(),
+            allow: Vec::new(),
+            compact: false,
+            rebalance: false,
+            scopes: Scopes::default(),
+            sources: BTreeMap::new(),
+            dead_entries: DeadEntries::default(),
+            python: PythonSettings::default(),
+            java: JavaSettings::default(),
+            tool_homes: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// The rank of a discovery source: lower is preferred.
+    pub fn source_rank(&self, source: &str) -> usize {
+        self.source_priority.iter()
+            .position(|s| s.eq_ignore_ascii_case(source))
+            .unwrap_or(self.source_priority.len())
+    }
+
+    /// Is this discovery source switched on?
+    pub fn source_enabled(&self, source: &str) -> bool {
+        self.sources.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(source))
+            .is_none_or(|(_, enabled)| *enabled)
+    }
+
+    /// Compiles the pin/block/allow globs, expanding `%VAR%` references via `lookup`.
+    ///
+    /// Invalid globs are skipped with a warning; `wanderlust config validate` reports them.
+    pub fn path_rules(&self, lookup: impl Fn(&str) -> Option<String>) -> PathRules {
+        let compile = |patterns: &[String]| -> Vec<(String, GlobMatcher)> {
+            patterns.iter()
+                .filter_map(|pattern| match compile_rule(pattern, &lookup) {
+                    Ok(matcher) => Some((pattern.trim().to_string(), matcher)),
+                    Err(e) => {
+                        warn!("Ignoring config rule '{}': {}", pattern, e);
+                        None
+                    }
+                })
+                .collect()
+        };
+        PathRules {
+            pin: compile(&self.pin),
+            block: compile(&self.block),
+            allow: compile(&self.allow),
+        }
+    }
+
+    /// Parses a config file. Unknown keys are ignored so that a config written for a
+    /// newer Wanderlust still loads; they are returned alongside the config instead.
+    pub fn from_toml(text: &str) -> Result<(Self, Vec<String>)> {
+        let mut unknown = Vec::new();
+        let deserializer = toml::Deserializer::new(text);
+        let config: Config = serde_ignored::deserialize(deserializer, |path| unknown.push(path.to_string()))?;
+        Ok((config, unknown))
+    }
+
+    /// Reads the config file at `path`. A missing file means the default config.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config from {:?}", path))?;
+        let (config, unknown) = Self::from_toml(&text)
+            .with_context(|| format!("{:?} is not a valid Wanderlust config", path))?;
+        for key in unknown {
+            warn!("Ignoring unknown config key '{}' in {:?}", key, path);
+        }
+        Ok(config)
+    }
+}
+
+/// The compiled pin/block/allow rules of a `Config`.
+///
+/// All methods take the canonical form of an entry (see `normalize::canonicalize`).
+pub struct PathRules {
+    pin: Vec<(String, GlobMatcher)>,
+    block: Vec<(String, GlobMatcher)>,
+    allow: Vec<(String, GlobMatcher)>,
+}
+
+impl PathRules {
+    /// The index of the first pin rule matching `key`, if any.
+    pub fn pin_rank(&self, key: &str) -> Option<usize> {
+        let key = glob_form(key);
+        self.pin.iter().position(|(_, m)| m.is_match(&key))
+    }
+
+    /// Is `key` blocked? Pins and `allow` rules win over `block`.
+    pub fn is_blocked(&self, key: &str) -> bool {
+        let glob_key = glob_form(key);
+        let matches = |rules: &[(String, GlobMatcher)]| rules.iter().any(|(_, m)| m.is_match(&glob_key));
+        matches(&self.block) && !matches(&self.allow) && self.pin_rank(key).is_none()
+    }
+
+    /// Pins without wildcards, as written: directories to keep even if undiscovered.
+    pub fn literal_pins(&self) -> impl Iterator<Item = &str> {
+        self.pin.iter()
+            .map(|(pattern, _)| pattern.as_str())
+            .filter(|pattern| !pattern.contains(['*', '?', '[', '{']))
+    }
+}
+
+/// Rules and entries are matched with `/` separators, so `\` is never read as a glob escape.
+fn glob_form(canonical: &str) -> String {
+    canonical.replace('\\', "/")
+}
+
+fn compile_rule(pattern: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<GlobMatcher> {
+    let glob = GlobBuilder::new(&glob_form(&normalize::canonicalize(pattern, lookup)))
+        .literal_separator(true)
+        .case_insensitive(true)
+        .build()?;
+    Ok(glob.compile_matcher())
+}
+
+/// Where the config file lives: `%APPDATA%\wanderlust\config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|dirs| dirs.config_dir().join("wanderlust").join("config.toml"))
+}
+
+/// Checks a config file and returns every problem found, for `wanderlust config validate`:
+/// syntax errors, unknown keys, invalid globs and unknown discovery sources.
+pub fn validate(text: &str) -> Vec<String> {
+    let (config, unknown) = match Config::from_toml(text) {
+        Ok(parsed) => parsed,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let mut problems: Vec<String> = unknown.into_iter()
+        .map(|key| format!("unknown key '{}'", key))
+        .collect();
+    for (list, patterns) in [("pin", &config.pin), ("block", &config.block), ("allow", &config.allow)] {
+        for pattern in patterns {
+            // Environment variables don't affect glob syntax, so they are left unexpanded here
+            if let Err(e) = compile_rule(pattern, |_| None) {
+                problems.push(format!("invalid glob in '{}': {}", list, e));
+            }
+        }
+    }
+    let sources = config.sources.keys().map(|s| ("sources", s))
+        .chain(config.source_priority.iter().map(|s| ("source_priority", s)));
+    for home in &config.tool_homes {
+        if home.name.is_empty() || home.path.is_empty() {
+            problems.push(format!("tool home '{}' needs both a name and a path", home.name));
+        }
+    }
+    for (list, source) in sources {
+        let known = KNOWN_SOURCES.iter().copied().chain(config.tool_homes.iter().map(|h| h.name.as_str()));
+        if !known.into_iter().any(|known| known.eq_ignore_ascii_case(source)) {
+            problems.push(format!("unknown discovery source '{}' in '{}'", source, list));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_rank() {
+        let config = Config::default();
+        assert!(config.source_rank("cargo") < config.source_rank("existing_path"));
+        assert_eq!(config.source_rank("SCOOP"), config.source_rank("scoop"));
+        assert_eq!(config.source_rank("unknown"), config.source_priority.len());
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let (config, unknown) = Config::from_toml(r#"
+            order = "source-priority"
+            pin = ['D:\corp\bin']
+            colour = "blue"
+
+            [scopes]
+            system = false
+
+            [sources]
+            scoop = false
+
+            [dead_entries]
+            grace_runs = 5
+        "#).unwrap();
+
+        assert_eq!(config.order, PathOrder::SourcePriority);
+        assert_eq!(config.pin, vec![r"D:\corp\bin".to_string()]);
+        assert!(config.scopes.user && !config.scopes.system);
+        assert!(!config.source_enabled("scoop") && config.source_enabled("cargo"));
+        assert_eq!(config.source_priority, Config::default().source_priority);
+        assert_eq!(config.dead_entries, DeadEntries { grace_runs: 5, grace_days: 7 });
+        assert_eq!(unknown, vec!["colour".to_string()]);
+        assert_eq!(Config::from_toml("").unwrap().0, Config::default());
+    }
+
+    #[test]
+    fn test_python_default() {
+        let parse = |text: &str| Config::from_toml(text).map(|(config, _)| config.python.default);
+        assert_eq!(parse("").unwrap(), PythonDefault::Auto);
+        assert_eq!(parse("[python]\ndefault = \"Conda\"").unwrap(), PythonDefault::Conda);
+        assert_eq!(parse("[python]\ndefault = \"3.11\"").unwrap(), PythonDefault::Version("3.11".to_string()));
+        assert!(parse("[python]\ndefault = \"latest\"").is_err());
+        assert!(parse("[python]\ndefault = \"3.\"").is_err());
+    }
+
+    #[test]
+    fn test_path_rules() {
+        let config = Config {
+            pin: vec![r"%CORP%\bin".to_string(), r"C:\Users\*\scoop\shims".to_string()],
+            block: vec![r"C:/Program Files/Oracle/**".to_string()],
+            allow: vec![r"C:\Program Files\Oracle\jdk-21\bin".to_string()],
+            ..Config::default()
+        };
+        let rules = config.path_rules(|name| (name == "CORP").then(|| r"D:\Corp".to_string()));
+
+        assert_eq!(rules.pin_rank(r"d:\corp\bin"), Some(0));
+        assert_eq!(rules.pin_rank(r"c:\users\me\scoop\shims"), Some(1));
+        assert_eq!(rules.pin_rank(r"c:\users\me\nested\scoop\shims"), None);
+        assert_eq!(rules.literal_pins().collect::<Vec<_>>(), vec![r"%CORP%\bin"]);
+
+        assert!(rules.is_blocked(r"c:\program files\oracle\javapath"));
+        assert!(!rules.is_blocked(r"c:\program files\oracle\jdk-21\bin"));
+        assert!(!rules.is_blocked(r"c:\program files\git\cmd"));
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let problems = validate(r#"
+            block = ['C:\Tools\[oops']
+            ordr = "alphabetical"
+            source_priority = ["cargo", "brew"]
+
+            [scopes]
+            sytem = false
+        "#);
+
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems.iter().any(|p| p.contains("'ordr'")));
+        assert!(problems.iter().any(|p| p.contains("'scopes.sytem'")));
+        assert!(problems.iter().any(|p| p.starts_with("invalid glob in 'block'")));
+        assert!(problems.iter().any(|p| p.contains("'brew'")));
+
+        assert!(validate("order = 'sideways'")[0].contains("sideways"));
+        assert!(validate("").is_empty());
+    }
+
+    #[test]
+    fn test_validate_accepts_configured_tool_homes() {
+        let problems = validate(r#"
+            source_priority = ["corp", "go"]
+            [[tool_homes]]
+            name = "corp"
+            path = '%USERPROFILE%\corp\bin'
+            [[tool_homes]]
+            name = ""
+            path = 'D:\bin'
+        "#);
+
+        assert_eq!(problems, vec!["tool home '' needs both a name and a path".to_string()]);
+    }
+}
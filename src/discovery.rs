@@ -8,26 +8,47 @@
 //!
 //! 1.  **Registry Scanning**: Checks `HKCU\Software\Microsoft\Windows\CurrentVersion\Uninstall`
 //!     to find installation locations of software (e.g., VS Code, Node.js).
-//! 2.  **Common Locations**: Checks "Well Known" paths like `~/.cargo/bin`, `~/.local/bin`,
-//!     and Scoop shims.
-//! 3.  **Existing PATH**: Ingests the current PATH to ensure we don't lose any manual configurations.
+//! 2.  **Common Locations**: Checks "Well Known" toolchain paths like `~/.cargo/bin`, `~/go/bin`
+//!     and Scoop shims (see `tool_homes`), and finds Python through PEP 514 and its version
+//!     managers (see `python`).
+//! 3.  **Package Managers**: winget's `Links` directories and portable package records,
+//!     Chocolatey's shims and its tools directory (`C:\tools`), and the stable directories
+//!     of the Node.js version managers (nvm-windows, fnm, volta).
+//! 4.  **Existing PATH**: Ingests the current PATH to ensure we don't lose any manual configurations.
+//! 5.  **JDKs**: Keeps only the `bin` of the JDK chosen by the config (see `java`).
 //!
 //! The result is a unified map of `Command Name -> List of Directories`.
+//!
+//! Every strategy reads the machine through `system::DiscoveryOps`, so the whole phase
+//! can be exercised against an in-memory `MockSystem` on any platform.
 
-use std::collections::HashMap;
-use std::path::PathBuf;
-use walkdir::WalkDir;
-use windows_registry::{CURRENT_USER, LOCAL_MACHINE};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use log::debug;
+use crate::config::Config;
+use crate::java;
+use crate::normalize;
+use crate::python;
+use crate::resolve;
+use crate::system::{DiscoveryOps, Hive};
+use crate::tool_homes;
+
+const UNINSTALL_KEY: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall";
+/// Machine-wide environment variables (HKLM), as installers set them.
+const MACHINE_ENVIRONMENT_KEY: &str = r"SYSTEM\CurrentControlSet\Control\Session Manager\Environment";
+/// Per-user environment variables (HKCU).
+const USER_ENVIRONMENT_KEY: &str = "Environment";
+/// How deep below `lib\<package>` Chocolatey packages keep the executables they shim.
+const CHOCOLATEY_LIB_DEPTH: usize = 4;
 
 /// Represents a potential location for a specific command.
 #[derive(Debug, Clone)]
 pub struct Candidate {
     /// The directory containing the executable.
     pub path: PathBuf,
-    /// The origin of this discovery (e.g., "scoop", "registry", "cargo").
-    /// This is currently used for debugging but will drive ranking logic in v2.0.
-    pub _source: String, 
+    /// The origin of this discovery (e.g., "scoop", "HKLM_Uninstall", "cargo").
+    /// Its position in `Config::source_priority` ranks the directory (see `Config::source_rank`).
+    pub source: String,
 }
 
 use crate::invariant_ppt::assert_invariant;
@@ -38,18 +59,32 @@ use crate::invariant_ppt::assert_invariant;
 /// - **Key**: The executable name (lowercase, e.g., "node", "cargo").
 /// - **Value**: A list of directories where this executable was found.
 ///
+/// All Registry, file system and environment access goes through `system`,
+/// so discovery can be run against a `MockSystem` fixture.
+///
+/// Candidates from sources switched off in `config`, or in directories it blocks, are dropped.
+///
 /// Use this map to construct a new PATH or to detect conflicts (shadowing).
-pub fn discover_candidates() -> HashMap<String, Vec<Candidate>> {
+pub fn discover_candidates(system: &impl DiscoveryOps, config: &Config) -> HashMap<String, Vec<Candidate>> {
     let mut map: HashMap<String, Vec<Candidate>> = HashMap::new();
 
     // 1. Scan Registry for installed programs
-    scan_registry_uninstall(&mut map);
+    scan_registry_uninstall(system, &mut map);
 
     // 2. Scan Common Locations (heuristic)
-    scan_common_locations(&mut map);
+    scan_tool_homes(system, &mut map, config);
+    python::scan(system, &config.python.default, &mut map);
 
-    // 3. Scan existing PATH (to not lose what we already have, just clean it)
-    scan_existing_path(&mut map);
+    // 3. Scan package managers
+    scan_winget(system, &mut map);
+    scan_chocolatey(system, &mut map);
+    scan_node_managers(system, &mut map);
+
+    // 4. Scan existing PATH (to not lose what we already have, just clean it)
+    scan_existing_path(system, &mut map);
+
+    // 5. Keep one JDK (the Uninstall scan and the PATH may have found several)
+    java::scan(system, &config.java.default, &mut map);
 
     // INVARIANT: We must have discovered *something*. An empty map implies a broken system or logic.
     assert_invariant(
@@ -67,148 +102,306 @@ pub fn discover_candidates() -> HashMap<String, Vec<Candidate>> {
         );
     }
 
+    drop_versioned_installs(system, &mut map);
+    apply_config(system, &mut map, config);
+
     map
 }
 
+/// Drops candidates from disabled sources and blocked directories.
+fn apply_config(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>, config: &Config) {
+    let rules = config.path_rules(|name| system.env_var(name));
+    for candidates in map.values_mut() {
+        candidates.retain(|c| {
+            let key = normalize::canonicalize(&c.path.to_string_lossy(), |name| system.env_var(name));
+            config.source_enabled(&c.source) && !rules.is_blocked(&key)
+        });
+    }
+    map.retain(|_, candidates| !candidates.is_empty());
+}
+
 /// Scans the Windows Registry for installed applications.
 ///
 /// Looks at `HKCU` and `HKLM` `Software\Microsoft\Windows\CurrentVersion\Uninstall` for `InstallLocation` keys.
 /// If a `bin` directory exists inside the install location, that is preferred.
-fn scan_registry_uninstall(map: &mut HashMap<String, Vec<Candidate>>) {
-    let key_path = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall";
+/// Records written by winget for portable packages are left to `scan_winget`.
+fn scan_registry_uninstall(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>) {
+    let key_path = UNINSTALL_KEY;
     
     // Check both HKCU (Current User) and HKLM (Local Machine / System-wide)
     let hives = [
-        (CURRENT_USER, "HKCU_Uninstall"), 
-        (LOCAL_MACHINE, "HKLM_Uninstall")
+        (Hive::CurrentUser, "HKCU_Uninstall"), 
+        (Hive::LocalMachine, "HKLM_Uninstall")
     ];
 
     for (hive, source_label) in hives {
-        if let Ok(uninstall_key) = hive.open(key_path) {
-            for subkey_name in uninstall_key.keys().into_iter().flatten() {
-                if let Ok(subkey) = uninstall_key.open(&subkey_name) {
-                    // Try "InstallLocation"
-                    if let Some(install_loc) = subkey.get_string("InstallLocation").ok().filter(|s| !s.is_empty()) {
-                        let path = PathBuf::from(&install_loc);
-                        // Heuristic: check if there's a 'bin' folder, otherwise use root
-                        let bin_path = path.join("bin");
-                        if bin_path.exists() {
-                            add_dir_candidates(map, &bin_path, source_label);
-                        } else if path.exists() {
-                            add_dir_candidates(map, &path, source_label);
-                        }
-                    }
+        for subkey_name in system.registry_subkeys(hive, key_path) {
+            let subkey_path = format!(r"{}\{}", key_path, subkey_name);
+            if system.registry_string(hive, &subkey_path, "WinGetPackageIdentifier").is_some() {
+                continue;
+            }
+            // Try "InstallLocation"
+            if let Some(install_loc) = system.registry_string(hive, &subkey_path, "InstallLocation").filter(|s| !s.is_empty()) {
+                let path = PathBuf::from(&install_loc);
+                // Heuristic: check if there's a 'bin' folder, otherwise use root
+                let bin_path = path.join("bin");
+                if system.is_dir(&bin_path) {
+                    add_dir_candidates(system, map, &bin_path, source_label);
+                } else if system.is_dir(&path) {
+                    add_dir_candidates(system, map, &path, source_label);
                 }
             }
         }
     }
 }
 
-/// Scans "well-known" directories that developers commonly use.
+/// Scans the directories toolchains install their commands into: `tool_homes::DEFAULT_TOOL_HOMES`
+/// (Cargo, Scoop, Go, .NET tools, npm, ...) and those added in `config`, each under its own name.
 ///
-/// Currently supports:
-/// - Cargo (`~/.cargo/bin`)
-/// - Local User Bin (`~/.local/bin`)
-/// - Scoop Shims (`~/scoop/shims`)
-fn scan_common_locations(map: &mut HashMap<String, Vec<Candidate>>) {
-    if let Some(user_profile) = directories::UserDirs::new() {
-        let home = user_profile.home_dir();
-        
-        // Cargo
-        let cargo_bin = home.join(".cargo").join("bin");
-        if cargo_bin.exists() {
-            add_dir_candidates(map, &cargo_bin, "cargo");
+/// Python has its own module, see `python::scan`.
+fn scan_tool_homes(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>, config: &Config) {
+    let mut seen = HashSet::new();
+    for home in tool_homes::all(&config.tool_homes) {
+        let Some(dir) = home.resolve(system) else {
+            continue;
+        };
+        // `%CARGO_HOME%\bin` and `~\.cargo\bin` are usually the same directory
+        if seen.insert(normalize::canonicalize(&dir.to_string_lossy(), |_| None)) {
+            add_dir_candidates(system, map, &dir, &home.name);
         }
+    }
+}
 
-        // Local bin
-        let local_bin = home.join(".local").join("bin");
-        if local_bin.exists() {
-            add_dir_candidates(map, &local_bin, "local_bin");
-        }
-        
-        // Scoop shims
-        let scoop_shims = home.join("scoop").join("shims");
-        if scoop_shims.exists() {
-             add_dir_candidates(map, &scoop_shims, "scoop");
+/// `%LOCALAPPDATA%`, or `AppData\Local` in the home directory if it is not set.
+pub(crate) fn local_app_data(system: &impl DiscoveryOps) -> Option<PathBuf> {
+    system.env_var("LOCALAPPDATA").map(PathBuf::from)
+        .or_else(|| system.home_dir().map(|home| home.join("AppData").join("Local")))
+}
+
+/// Scans winget's portable packages, per user (`%LOCALAPPDATA%\Microsoft\WinGet`) and
+/// per machine (`%ProgramFiles%\WinGet`).
+///
+/// Winget links every command of a portable package into a `Links` directory, which is what
+/// belongs on the PATH. When it can't create links, it puts the package directory itself
+/// (under `Packages`) on the PATH instead, and records that as `InstallDirectoryAddedToPath`
+/// in the package's Uninstall record.
+fn scan_winget(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>) {
+    let roots = local_app_data(system).map(|dir| dir.join("Microsoft").join("WinGet")).into_iter()
+        .chain(["ProgramFiles", "ProgramFiles(x86)"].iter()
+            .filter_map(|name| system.env_var(name))
+            .map(|dir| PathBuf::from(dir).join("WinGet")));
+    for root in roots {
+        let links = root.join("Links");
+        if system.is_dir(&links) {
+            add_dir_candidates(system, map, &links, "winget");
         }
+    }
 
-        // Python installations (Windows Store and python.org installer)
-        // User installs go to: %LOCALAPPDATA%\Programs\Python\Python3XX\
-        let python_base = home.join("AppData").join("Local").join("Programs").join("Python");
-        if python_base.exists() {
-            if let Ok(entries) = std::fs::read_dir(&python_base) {
-                for entry in entries.filter_map(|e| e.ok()) {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        let name = path.file_name().unwrap_or_default().to_string_lossy();
-                        // Match Python3XX directories (not Launcher)
-                        if name.starts_with("Python3") {
-                            add_dir_candidates(map, &path, "python");
-                            // Also add Scripts subdirectory (pip, etc.)
-                            let scripts = path.join("Scripts");
-                            if scripts.exists() {
-                                add_dir_candidates(map, &scripts, "python_scripts");
-                            }
-                        }
-                    }
+    for hive in [Hive::CurrentUser, Hive::LocalMachine] {
+        for subkey_name in system.registry_subkeys(hive, UNINSTALL_KEY) {
+            let subkey_path = format!(r"{}\{}", UNINSTALL_KEY, subkey_name);
+            let record = |name| system.registry_string(hive, &subkey_path, name);
+            if record("WinGetPackageIdentifier").is_none() || record("InstallDirectoryAddedToPath").as_deref() != Some("1") {
+                continue;
+            }
+            if let Some(install_loc) = record("InstallLocation").filter(|s| !s.is_empty()) {
+                let path = PathBuf::from(install_loc);
+                if system.is_dir(&path) {
+                    add_dir_candidates(system, map, &path, "winget");
                 }
             }
         }
     }
-    
-    // System-wide Python installations
-    for drive in ["C:", "D:"] {
-        // Old-style: C:\Python3XX
-        let drive_path = PathBuf::from(drive);
-        if let Ok(entries) = std::fs::read_dir(&drive_path) {
-            for entry in entries.filter_map(|e| e.ok()) {
-                let path = entry.path();
-                if path.is_dir() {
-                    let name = path.file_name().unwrap_or_default().to_string_lossy();
-                    if name.starts_with("Python3") {
-                        add_dir_candidates(map, &path, "python_system");
-                        let scripts = path.join("Scripts");
-                        if scripts.exists() {
-                            add_dir_candidates(map, &scripts, "python_scripts");
-                        }
-                    }
+}
+
+/// A variable from the process environment or, for a process started before an installer
+/// set it, from the user or machine environment in the Registry.
+pub(crate) fn environment_variable(system: &impl DiscoveryOps, name: &str) -> Option<String> {
+    system.env_var(name)
+        .or_else(|| system.registry_string(Hive::CurrentUser, USER_ENVIRONMENT_KEY, name))
+        .or_else(|| system.registry_string(Hive::LocalMachine, MACHINE_ENVIRONMENT_KEY, name))
+        .filter(|value| !value.is_empty())
+}
+
+/// Where Chocolatey is installed: `%ChocolateyInstall%`, or its default `%ProgramData%\chocolatey`.
+pub fn chocolatey_root(system: &impl DiscoveryOps) -> Option<PathBuf> {
+    environment_variable(system, "ChocolateyInstall")
+        .map(PathBuf::from)
+        .or_else(|| system.env_var("ProgramData").map(|dir| PathBuf::from(dir).join("chocolatey")))
+        .filter(|root| system.is_dir(root))
+}
+
+/// Scans Chocolatey: the shims in `<root>\bin` (source `chocolatey`), and the packages that
+/// unpack into the tools directory, `%ChocolateyToolsLocation%` or `C:\tools` (source
+/// `chocolatey_tools`). Like for Uninstall records, a package's `bin` directory is preferred.
+///
+/// Without Chocolatey, `C:\tools` is just some folder, so it is only scanned if Chocolatey is installed.
+fn scan_chocolatey(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>) {
+    let root = chocolatey_root(system);
+    if let Some(root) = &root {
+        let shims = root.join("bin");
+        if system.is_dir(&shims) {
+            add_dir_candidates(system, map, &shims, "chocolatey");
+        }
+    }
+
+    let tools = match environment_variable(system, "ChocolateyToolsLocation") {
+        Some(tools) => PathBuf::from(tools),
+        None if root.is_some() => PathBuf::from(format!(r"{}\tools", system.env_var("SystemDrive").unwrap_or_else(|| "C:".to_string()))),
+        None => return,
+    };
+    for package in system.list_dir(&tools) {
+        if !system.is_dir(&package) {
+            continue;
+        }
+        let bin = package.join("bin");
+        let dir = if system.is_dir(&bin) { bin } else { package };
+        add_dir_candidates(system, map, &dir, "chocolatey_tools");
+    }
+}
+
+/// Maps each Chocolatey shim to the executable it launches, keyed by the shim's canonical
+/// path (see `normalize::canonicalize`).
+///
+/// Chocolatey generates a shim in `<root>\bin` for every `.exe` a package puts under
+/// `<root>\lib\<package>`, except those next to a `<name>.exe.ignore` file. A shim whose
+/// name matches executables in two packages is left out, since which one it runs is unknown.
+pub fn chocolatey_shims(system: &impl DiscoveryOps) -> HashMap<String, PathBuf> {
+    let Some(root) = chocolatey_root(system) else {
+        return HashMap::new();
+    };
+    let mut executables: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut pending: Vec<(PathBuf, usize)> = system.list_dir(&root.join("lib")).into_iter()
+        .filter(|p| system.is_dir(p))
+        .map(|p| (p, 0))
+        .collect();
+    while let Some((dir, depth)) = pending.pop() {
+        for path in system.list_dir(&dir) {
+            if system.is_dir(&path) {
+                if depth < CHOCOLATEY_LIB_DEPTH {
+                    pending.push((path, depth + 1));
                 }
+                continue;
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+            let ignored = system.is_file(&dir.join(format!("{}.ignore", path.file_name().unwrap_or_default().to_string_lossy())));
+            if name.ends_with(".exe") && !ignored {
+                executables.entry(name).or_default().push(path);
             }
         }
-        
-        // Program Files style: C:\Program Files\Python3XX
-        for pf in ["Program Files", "Program Files (x86)"] {
-            let pf_path = drive_path.join(pf);
-            if let Ok(entries) = std::fs::read_dir(&pf_path) {
-                for entry in entries.filter_map(|e| e.ok()) {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        let name = path.file_name().unwrap_or_default().to_string_lossy();
-                        if name.starts_with("Python3") {
-                            add_dir_candidates(map, &path, "python_system");
-                            let scripts = path.join("Scripts");
-                            if scripts.exists() {
-                                add_dir_candidates(map, &scripts, "python_scripts");
-                            }
-                        }
-                    }
-                }
+    }
+
+    system.list_dir(&root.join("bin")).into_iter()
+        .filter(|shim| system.is_file(shim))
+        .filter_map(|shim| {
+            let name = shim.file_name()?.to_string_lossy().to_lowercase();
+            match executables.get(&name)?.as_slice() {
+                [target] => Some((normalize::canonicalize(&shim.to_string_lossy(), |_| None), target.clone())),
+                _ => None,
             }
+        })
+        .collect()
+}
+
+/// Where the Node.js version managers keep their data: `(nvm, fnm, volta)`.
+///
+/// * nvm-windows: `%NVM_HOME%`, holding `nvm.exe` and one `v<version>` directory per install.
+/// * fnm: `%FNM_DIR%`, or `%APPDATA%\fnm`, holding `node-versions` and `aliases`.
+/// * volta: `%VOLTA_HOME%`, or `%LOCALAPPDATA%\Volta`, holding the `bin` shims and `tools`.
+fn node_manager_homes(system: &impl DiscoveryOps) -> (Option<PathBuf>, Option<PathBuf>, Option<PathBuf>) {
+    let home = |name: &str, default: Option<PathBuf>| {
+        environment_variable(system, name).map(PathBuf::from)
+            .or(default)
+            .filter(|dir| system.is_dir(dir))
+    };
+    let app_data = system.env_var("APPDATA").map(PathBuf::from)
+        .or_else(|| system.home_dir().map(|home| home.join("AppData").join("Roaming")));
+    (
+        home("NVM_HOME", None),
+        home("FNM_DIR", app_data.map(|dir| dir.join("fnm"))),
+        home("VOLTA_HOME", local_app_data(system).map(|dir| dir.join("Volta"))),
+    )
+}
+
+/// Scans the Node.js version managers, adding only the directory each one keeps stable while
+/// versions are switched:
+///
+/// * nvm-windows: `%NVM_HOME%` (for `nvm.exe`) and `%NVM_SYMLINK%`, the junction to the active version.
+/// * fnm: `aliases\default`, the link to the default version.
+/// * volta: `bin`, the shims that pick a version per project.
+fn scan_node_managers(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>) {
+    let (nvm, fnm, volta) = node_manager_homes(system);
+    let symlink = nvm.as_ref().and(environment_variable(system, "NVM_SYMLINK")).map(PathBuf::from);
+    let dirs = [
+        (nvm, "nvm"),
+        (symlink, "nvm"),
+        (fnm.map(|dir| dir.join("aliases").join("default")), "fnm"),
+        (volta.map(|dir| dir.join("bin")), "volta"),
+    ];
+    for (dir, source) in dirs {
+        if let Some(dir) = dir.filter(|dir| system.is_dir(dir)) {
+            add_dir_candidates(system, map, &dir, source);
         }
     }
 }
 
+/// Drops candidates inside a version manager's versioned installs, wherever they were found
+/// (an Uninstall record, the PATH of a shell the manager set up). On the PATH, such a directory
+/// would pin one version and fight the manager's switching:
+///
+/// * `%NVM_HOME%\v<version>`
+/// * fnm's `node-versions`, and the per-shell `fnm_multishells` links, which vanish with the shell
+/// * volta's `tools`
+/// * pyenv's `versions` and conda's `envs` (see `python::versioned_roots`)
+fn drop_versioned_installs(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>) {
+    let (nvm, fnm, volta) = node_manager_homes(system);
+    let multishell = system.env_var("FNM_MULTISHELL_PATH")
+        .and_then(|link| Path::new(&link).parent().map(Path::to_path_buf));
+    let key = |path: &Path| normalize::canonicalize(&path.to_string_lossy(), |name| system.env_var(name));
+    let versioned: Vec<String> = [
+        nvm,
+        fnm.map(|dir| dir.join("node-versions")),
+        local_app_data(system).map(|dir| dir.join("fnm_multishells")),
+        multishell,
+        volta.map(|dir| dir.join("tools")),
+    ]
+    .iter()
+    .flatten()
+    .chain(&python::versioned_roots(system))
+    .map(|dir| key(dir))
+    .filter(|root| !root.is_empty())
+    .collect();
+    if versioned.is_empty() {
+        return;
+    }
+
+    for candidates in map.values_mut() {
+        candidates.retain(|c| {
+            let dir = key(&c.path);
+            let inside = versioned.iter().any(|root| {
+                let prefix = if root.ends_with('\\') { root.clone() } else { format!("{}\\", root) };
+                dir.starts_with(&prefix)
+            });
+            if inside {
+                debug!("Skipping {:?} ({}): a versioned install managed by a version manager", c.path, c.source);
+            }
+            !inside
+        });
+    }
+    map.retain(|_, candidates| !candidates.is_empty());
+}
+
 /// Scans the current environment variable `PATH`.
 ///
 /// This ensures that even if we don't heuristically find a tool,
 /// if the user had it in their PATH before, we preserve it.
-fn scan_existing_path(map: &mut HashMap<String, Vec<Candidate>>) {
-    if let Ok(path_var) = std::env::var("PATH") {
+fn scan_existing_path(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>) {
+    if let Some(path_var) = system.env_var("PATH") {
         for part in path_var.split(';') {
             if part.is_empty() { continue; }
             let path = PathBuf::from(part);
-            if path.exists() {
-                add_dir_candidates(map, &path, "existing_path");
+            if system.is_dir(&path) {
+                add_dir_candidates(system, map, &path, "existing_path");
             }
         }
     }
@@ -216,31 +409,306 @@ fn scan_existing_path(map: &mut HashMap<String, Vec<Candidate>>) {
 
 /// Helper function to scan a specific directory for executables.
 ///
-/// Adds any found `.exe`, `.cmd`, `.bat`, or `.com` files to the candidate map.
-/// This function is shallow (depth 1) generally, to avoid massive crawls.
-fn add_dir_candidates(map: &mut HashMap<String, Vec<Candidate>>, dir: &PathBuf, source: &str) {
+/// Adds any file whose extension is in `PATHEXT` (see `resolve::pathext`) to the candidate map,
+/// so a `.py` or `.ps1` added there counts just like `.exe`.
+/// This function is shallow (depth 1), to avoid massive crawls.
+pub(crate) fn add_dir_candidates(system: &impl DiscoveryOps, map: &mut HashMap<String, Vec<Candidate>>, dir: &Path, source: &str) {
     debug!("Scanning directory: {:?}", dir);
+    let pathext = resolve::pathext(system);
     // Only go 1 level deep
-    let walker = WalkDir::new(dir).max_depth(1);
-    
-    for entry in walker.into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if !path.is_file() {
+    for path in system.list_dir(dir) {
+        if !system.is_file(&path) {
             continue;
         }
 
         if let (Some(stem), Some(ext)) = (path.file_stem(), path.extension()) {
-            let ext_str = ext.to_string_lossy().to_lowercase();
-            // We only care about executables for Windows
-            if ext_str == "exe" || ext_str == "cmd" || ext_str == "bat" || ext_str == "com" {
+            let ext_str = format!(".{}", ext.to_string_lossy().to_lowercase());
+            // We only care about what Windows would run by bare name
+            if pathext.contains(&ext_str) {
                 let cmd_name = stem.to_string_lossy().to_lowercase();
                 
                 // Add to map
                 map.entry(cmd_name).or_default().push(Candidate {
                     path: dir.to_path_buf(), // Store the *directory* containing the tool
-                    _source: source.to_string(),
+                    source: source.to_string(),
                 });
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    const UNINSTALL: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall";
+
+    fn fixture() -> MockSystem {
+        let system = MockSystem::new();
+        system.set_home("/home/test");
+        system
+    }
+
+    fn sources_for<'a>(map: &'a HashMap<String, Vec<Candidate>>, cmd: &str) -> Vec<(&'a Path, &'a str)> {
+        map.get(cmd)
+            .map(|c| c.iter().map(|c| (c.path.as_path(), c.source.as_str())).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_discovers_cargo_and_scoop() {
+        let system = fixture();
+        system.add_file("/home/test/.cargo/bin/cargo.exe");
+        system.add_file("/home/test/.cargo/bin/README.md");
+        system.add_file("/home/test/scoop/shims/rg.EXE");
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "cargo"), vec![(Path::new("/home/test/.cargo/bin"), "cargo")]);
+        assert_eq!(sources_for(&map, "rg"), vec![(Path::new("/home/test/scoop/shims"), "scoop")]);
+        assert!(!map.contains_key("readme"), "Non-executables must be ignored");
+    }
+
+    #[test]
+    fn test_discovers_tool_homes_from_defaults_and_config() {
+        let system = fixture();
+        system.set_env("APPDATA", "/home/test/AppData/Roaming");
+        system.set_env("CARGO_HOME", "/home/test/.cargo");
+        system.add_file("/home/test/.cargo/bin/cargo.exe");
+        system.add_file("/home/test/go/bin/gopls.exe");
+        system.add_file("/home/test/.dotnet/tools/dotnet-ef.exe");
+        system.add_file("/home/test/AppData/Roaming/npm/tsc.cmd");
+        system.add_file("/home/test/corp/bin/deploy.exe");
+        let (config, _) = Config::from_toml(r#"
+            [[tool_homes]]
+            name = "corp"
+            path = '~\corp\bin'
+            detect = "deploy.exe"
+        "#).unwrap();
+
+        let map = discover_candidates(&system, &config);
+
+        assert_eq!(sources_for(&map, "cargo"), vec![(Path::new("/home/test/.cargo/bin"), "cargo")], "Found once through CARGO_HOME");
+        assert_eq!(sources_for(&map, "gopls"), vec![(Path::new("/home/test/go/bin"), "go")]);
+        assert_eq!(sources_for(&map, "dotnet-ef"), vec![(Path::new("/home/test/.dotnet/tools"), "dotnet_tools")]);
+        assert_eq!(sources_for(&map, "tsc"), vec![(Path::new("/home/test/AppData/Roaming/npm"), "npm")]);
+        assert_eq!(sources_for(&map, "deploy"), vec![(Path::new("/home/test/corp/bin"), "corp")]);
+    }
+
+    #[test]
+    fn test_discovers_user_python_and_scripts() {
+        let system = fixture();
+        let python = "/home/test/AppData/Local/Programs/Python";
+        system.add_file(format!("{}/Python312/python.exe", python));
+        system.add_file(format!("{}/Python312/Scripts/pip.exe", python));
+        system.add_file(format!("{}/Launcher/py.exe", python));
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "python"), vec![(Path::new("/home/test/AppData/Local/Programs/Python/Python312"), "python")]);
+        assert_eq!(sources_for(&map, "pip"), vec![(Path::new("/home/test/AppData/Local/Programs/Python/Python312/Scripts"), "python_scripts")]);
+        assert_eq!(sources_for(&map, "py"), vec![(Path::new("/home/test/AppData/Local/Programs/Python/Launcher"), "py_launcher")],
+            "The launcher is found, but not as an interpreter");
+    }
+
+    #[test]
+    fn test_discovers_uninstall_key_prefers_bin() {
+        let system = fixture();
+        system.set_registry_string(Hive::LocalMachine, &format!(r"{}\NodeJS", UNINSTALL), "InstallLocation", "/opt/node");
+        system.set_registry_string(Hive::CurrentUser, &format!(r"{}\Tool", UNINSTALL), "InstallLocation", "/opt/tool");
+        system.set_registry_string(Hive::CurrentUser, &format!(r"{}\Empty", UNINSTALL), "InstallLocation", "");
+        system.add_file("/opt/node/node.exe");
+        system.add_file("/opt/tool/bin/tool.cmd");
+        system.add_file("/opt/tool/setup.exe");
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "node"), vec![(Path::new("/opt/node"), "HKLM_Uninstall")]);
+        assert_eq!(sources_for(&map, "tool"), vec![(Path::new("/opt/tool/bin"), "HKCU_Uninstall")]);
+        assert!(!map.contains_key("setup"), "Install root is skipped when a bin directory exists");
+    }
+
+    #[test]
+    fn test_discovers_winget_links_and_unlinked_packages() {
+        let system = fixture();
+        system.set_env("LOCALAPPDATA", "/home/test/AppData/Local");
+        system.set_env("ProgramFiles", "/opt/pf");
+        system.add_file("/home/test/AppData/Local/Microsoft/WinGet/Links/jq.exe");
+        system.add_file("/opt/pf/WinGet/Links/gh.exe");
+        let packages = "/home/test/AppData/Local/Microsoft/WinGet/Packages";
+        system.add_file(format!("{}/jqlang.jq_Microsoft.Winget.Source_8wekyb3d8bbwe/jq.exe", packages));
+        system.add_file(format!("{}/BurntSushi.ripgrep.MSVC_Microsoft.Winget.Source_8wekyb3d8bbwe/rg.exe", packages));
+        let record = |name: &str, loc: &str, added_to_path: &str| {
+            let key = format!(r"{}\{}", UNINSTALL, name);
+            system.set_registry_string(Hive::CurrentUser, &key, "WinGetPackageIdentifier", name);
+            system.set_registry_string(Hive::CurrentUser, &key, "InstallLocation", loc);
+            system.set_registry_string(Hive::CurrentUser, &key, "InstallDirectoryAddedToPath", added_to_path);
+        };
+        record("jqlang.jq", &format!("{}/jqlang.jq_Microsoft.Winget.Source_8wekyb3d8bbwe", packages), "0");
+        record("BurntSushi.ripgrep.MSVC", &format!("{}/BurntSushi.ripgrep.MSVC_Microsoft.Winget.Source_8wekyb3d8bbwe", packages), "1");
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "jq"), vec![(Path::new("/home/test/AppData/Local/Microsoft/WinGet/Links"), "winget")],
+            "Linked packages are found through Links only");
+        assert_eq!(sources_for(&map, "gh"), vec![(Path::new("/opt/pf/WinGet/Links"), "winget")]);
+        assert_eq!(sources_for(&map, "rg"), vec![(Path::new("/home/test/AppData/Local/Microsoft/WinGet/Packages/BurntSushi.ripgrep.MSVC_Microsoft.Winget.Source_8wekyb3d8bbwe"), "winget")]);
+    }
+
+    #[test]
+    fn test_discovers_chocolatey_shims_and_tools() {
+        let system = fixture();
+        system.set_registry_string(Hive::LocalMachine, MACHINE_ENVIRONMENT_KEY, "ChocolateyInstall", "/opt/choco");
+        system.set_env("ChocolateyToolsLocation", "/tools");
+        system.add_file("/opt/choco/bin/rg.exe");
+        system.add_file("/opt/choco/bin/7z.exe");
+        system.add_file("/tools/neovim/bin/nvim.exe");
+        system.add_file("/tools/flutter/flutter.bat");
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "rg"), vec![(Path::new("/opt/choco/bin"), "chocolatey")],
+            "Root found through the machine environment");
+        assert_eq!(sources_for(&map, "nvim"), vec![(Path::new("/tools/neovim/bin"), "chocolatey_tools")]);
+        assert_eq!(sources_for(&map, "flutter"), vec![(Path::new("/tools/flutter"), "chocolatey_tools")]);
+    }
+
+    #[test]
+    fn test_tools_directory_needs_chocolatey() {
+        let system = fixture();
+        system.set_env("SystemDrive", "/drive");
+        system.add_file("/drive\\tools/unrelated/unrelated.exe");
+        system.add_file("/home/test/.cargo/bin/cargo.exe");
+
+        let map = discover_candidates(&system, &Config::default());
+        assert!(sources_for(&map, "unrelated").is_empty(), "No Chocolatey, so C:\\tools is not its tools directory");
+
+        system.set_env("ChocolateyInstall", "/opt/choco");
+        system.add_dir("/opt/choco/bin");
+        let map = discover_candidates(&system, &Config::default());
+        assert_eq!(sources_for(&map, "unrelated"), vec![(Path::new("/drive\\tools/unrelated"), "chocolatey_tools")]);
+    }
+
+    #[test]
+    fn test_chocolatey_shims_resolve_to_package_executables() {
+        let system = fixture();
+        system.set_env("ChocolateyInstall", "/opt/choco");
+        system.add_file("/opt/choco/bin/rg.exe");
+        system.add_file("/opt/choco/bin/7z.exe");
+        system.add_file("/opt/choco/bin/setup.exe");
+        system.add_file("/opt/choco/bin/choco.exe");
+        system.add_file("/opt/choco/lib/ripgrep/tools/ripgrep-14.1.0-x86_64-pc-windows-msvc/rg.exe");
+        system.add_file("/opt/choco/lib/7zip.portable/tools/7z.exe");
+        system.add_file("/opt/choco/lib/a/tools/setup.exe");
+        system.add_file("/opt/choco/lib/b/tools/setup.exe");
+        system.add_file("/opt/choco/lib/7zip.portable/tools/7zfm.exe");
+        system.add_file("/opt/choco/lib/7zip.portable/tools/7zfm.exe.ignore");
+
+        let shims = chocolatey_shims(&system);
+        let target = |shim: &str| shims.get(&normalize::canonicalize(shim, |_| None)).cloned();
+
+        assert_eq!(target("/opt/choco/bin/rg.exe"), Some(PathBuf::from("/opt/choco/lib/ripgrep/tools/ripgrep-14.1.0-x86_64-pc-windows-msvc/rg.exe")));
+        assert_eq!(target("/opt/choco/bin/7z.exe"), Some(PathBuf::from("/opt/choco/lib/7zip.portable/tools/7z.exe")));
+        assert_eq!(target("/opt/choco/bin/setup.exe"), None, "Two packages ship a setup.exe");
+        assert_eq!(target("/opt/choco/bin/choco.exe"), None, "Not from a package");
+        assert_eq!(shims.len(), 2);
+    }
+
+    #[test]
+    fn test_node_managers_add_stable_dirs_only() {
+        let system = fixture();
+        let roaming = "/home/test/AppData/Roaming";
+        let local = "/home/test/AppData/Local";
+        system.set_env("APPDATA", roaming);
+        system.set_env("LOCALAPPDATA", local);
+        system.set_registry_string(Hive::CurrentUser, USER_ENVIRONMENT_KEY, "NVM_HOME", &format!("{}/nvm", roaming));
+        system.set_registry_string(Hive::CurrentUser, USER_ENVIRONMENT_KEY, "NVM_SYMLINK", "/opt/nodejs");
+        system.add_file(format!("{}/nvm/nvm.exe", roaming));
+        system.add_file(format!("{}/nvm/v18.17.0/node.exe", roaming));
+        system.add_file("/opt/nodejs/node.exe");
+        system.add_file(format!("{}/fnm/aliases/default/node.exe", roaming));
+        system.add_file(format!("{}/fnm/node-versions/v20.11.0/installation/node.exe", roaming));
+        system.add_file(format!("{}/fnm_multishells/1234_1700000000000/node.exe", local));
+        system.add_file(format!("{}/Volta/bin/node.exe", local));
+        system.add_file(format!("{}/Volta/tools/image/node/20.11.0/node.exe", local));
+        system.set_registry_string(Hive::CurrentUser, &format!(r"{}\Node", UNINSTALL), "InstallLocation", &format!("{}/nvm/v18.17.0", roaming));
+        system.set_env("PATH", &format!(
+            "{}/fnm_multishells/1234_1700000000000;{}/Volta/tools/image/node/20.11.0;{}/fnm/node-versions/v20.11.0/installation",
+            local, local, roaming,
+        ));
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "nvm"), vec![(Path::new("/home/test/AppData/Roaming/nvm"), "nvm")]);
+        assert_eq!(sources_for(&map, "node"), vec![
+            (Path::new("/opt/nodejs"), "nvm"),
+            (Path::new("/home/test/AppData/Roaming/fnm/aliases/default"), "fnm"),
+            (Path::new("/home/test/AppData/Local/Volta/bin"), "volta"),
+        ], "Versioned installs from the Uninstall record and the shell's PATH are dropped");
+    }
+
+    #[test]
+    fn test_node_versions_kept_without_a_manager() {
+        let system = fixture();
+        system.add_file("/opt/node/v18.17.0/node.exe");
+        system.set_env("PATH", "/opt/node/v18.17.0");
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "node"), vec![(Path::new("/opt/node/v18.17.0"), "existing_path")]);
+    }
+
+    #[test]
+    fn test_existing_path_skips_missing_dirs() {
+        let system = fixture();
+        system.add_file("/usr/tools/make.exe");
+        system.set_env("PATH", "/usr/tools;;/does/not/exist");
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert_eq!(sources_for(&map, "make"), vec![(Path::new("/usr/tools"), "existing_path")]);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_executable_extensions_come_from_pathext() {
+        let system = fixture();
+        system.add_file("/home/test/.cargo/bin/tool.py");
+        system.add_file("/home/test/.cargo/bin/legacy.bat");
+        system.add_file("/home/test/.cargo/bin/setup.js");
+        system.set_env("PATHEXT", ".EXE;.PY");
+
+        let map = discover_candidates(&system, &Config::default());
+
+        assert!(map.contains_key("tool"));
+        assert!(!map.contains_key("legacy"), ".bat is not in this PATHEXT");
+
+        system.env.lock().unwrap().remove("PATHEXT");
+        let map = discover_candidates(&system, &Config::default());
+        assert!(map.contains_key("legacy") && map.contains_key("setup"), "Default PATHEXT includes .bat and .js");
+    }
+
+    #[test]
+    fn test_config_disables_sources_and_blocks_dirs() {
+        let system = fixture();
+        system.add_file("/home/test/.cargo/bin/cargo.exe");
+        system.add_file("/home/test/scoop/shims/rg.exe");
+        system.add_file("/opt/oracle/java/java.exe");
+        system.add_file("/opt/oracle/jdk/javac.exe");
+        system.set_env("PATH", "/opt/oracle/java;/opt/oracle/jdk");
+        let (config, _) = Config::from_toml(r#"
+            block = ["/opt/oracle/**"]
+            allow = ["/opt/oracle/jdk"]
+            [sources]
+            scoop = false
+        "#).unwrap();
+
+        let map = discover_candidates(&system, &config);
+
+        assert!(map.contains_key("cargo"));
+        assert!(!map.contains_key("rg"), "scoop is switched off");
+        assert!(!map.contains_key("java"), "blocked");
+        assert!(map.contains_key("javac"), "allowed despite the block");
+    }
+}
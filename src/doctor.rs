@@ -0,0 +1,669 @@
+//! # Doctor
+//!
+//! Read-only PATH diagnostics. `build_report` collects everything into a `DoctorReport`,
+//! which is rendered as text for people, or as JSON or Markdown for fleet scripts and
+//! compliance checks.
+//!
+//! Every problem is also a `Finding` with a stable code and a severity, so scripts can
+//! fail on "anything at warning or above" without parsing prose:
+//!
+//! | Code  | Severity | Meaning |
+//! |-------|----------|---------|
+//! | WL001 | warning  | An entry appears twice in the same PATH |
+//! | WL002 | warning  | A User PATH entry is already in the System PATH |
+//! | WL003 | warning  | An entry points to a directory that does not exist |
+//! | WL004 | info     | A command exists in more than one PATH directory |
+//! | WL005 | warning  | The combined PATH exceeds 2047 characters (legacy tools truncate it) |
+//! | WL006 | error    | The combined PATH exceeds 32767 characters (Windows truncates it) |
+//! | WL007 | info     | The combined PATH is within 10% of 2047 characters |
+//! | WL008 | warning  | The combined PATH is within 10% of 32767 characters |
+//! | WL009 | warning  | `python` runs the Microsoft Store stub instead of an installed Python (info if none is installed) |
+//! | WL010 | warning  | `java` runs Oracle's `javapath` shim although a JDK is later on the PATH |
+//! | WL011 | info     | `JAVA_HOME` points at a different Java than the `java` command runs |
+//!
+//! The limits and lengths come from `budget`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use serde::Serialize;
+use crate::budget::{self, LengthBudget};
+use crate::discovery;
+use crate::java;
+use crate::normalize;
+use crate::plan::Scope;
+use crate::python;
+use crate::resolve;
+use crate::system::{DiscoveryOps, SystemOps};
+#[cfg(windows)]
+use crate::config::Config;
+#[cfg(windows)]
+use crate::system::WindowsSystem;
+
+/// How bad a finding is. Ordered, so `--fail-on warning` also fails on errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// How to render a `DoctorReport`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+    Markdown,
+}
+
+/// One problem, with a stable code (see the module docs).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// An entry in a PATH value, as written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub scope: Scope,
+    pub entry: String,
+}
+
+/// A command found in more than one PATH directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ShadowedCommand {
+    pub command: String,
+    /// The file Windows runs.
+    pub winner: PathBuf,
+    /// The files that never run, in lookup order.
+    pub shadowed: Vec<PathBuf>,
+    /// The real executable behind each of the files above that is a package manager shim.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl ShadowedCommand {
+    /// `file`, followed by the executable it launches if it is a shim.
+    fn describe(&self, file: &Path) -> String {
+        match self.targets.get(file) {
+            Some(target) => format!("{} (shim for {})", file.display(), target.display()),
+            None => file.display().to_string(),
+        }
+    }
+}
+
+/// A Python command that resolves to the Microsoft Store stub (see `python::is_store_stub`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoreStub {
+    pub command: String,
+    pub stub: PathBuf,
+    /// Real interpreters later on the PATH that the stub hides.
+    pub shadowed: Vec<PathBuf>,
+}
+
+/// Which `java` runs, and how that fits with Oracle's `javapath` shims and `JAVA_HOME`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JavaCheck {
+    /// The file the `java` command runs.
+    pub java: PathBuf,
+    /// JDK executables later on the PATH, if `java` is a `javapath` shim.
+    pub hidden_by_javapath: Vec<PathBuf>,
+    /// `JAVA_HOME`, if set.
+    pub java_home: Option<String>,
+    /// False if `JAVA_HOME` is set but `java` is not in its `bin` folder.
+    pub java_home_matches: bool,
+}
+
+/// Everything `wanderlust doctor` knows about the PATH.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DoctorReport {
+    pub system_entries: usize,
+    pub user_entries: usize,
+    /// Entries in the PATH of the process running doctor, which may differ from the stored one.
+    pub session_entries: Option<usize>,
+    /// Later occurrences of an entry already in the same PATH.
+    pub duplicates: Vec<Entry>,
+    /// User PATH entries that are also in the System PATH.
+    pub overlaps: Vec<String>,
+    /// Entries whose directory does not exist.
+    pub missing: Vec<Entry>,
+    pub shadows: Vec<ShadowedCommand>,
+    pub store_stubs: Vec<StoreStub>,
+    pub java: Option<JavaCheck>,
+    pub length: LengthBudget,
+    pub findings: Vec<Finding>,
+}
+
+/// Runs the doctor against the real machine and prints the report in `format`.
+///
+/// This does not modify the system. Discovery honours `config` (e.g. disabled sources).
+#[cfg(windows)]
+pub fn doctor(config: &Config, format: ReportFormat) -> DoctorReport {
+    let system = WindowsSystem;
+    let candidates_map = discovery::discover_candidates(&system, config);
+    let report = build_report(&system, &candidates_map);
+    print!("{}", report.render(format));
+    report
+}
+
+/// Collects the report. Only reads from `system`.
+///
+/// `candidates_map` supplies the command names checked for shadowing.
+pub fn build_report(
+    system: &(impl SystemOps + DiscoveryOps),
+    candidates_map: &HashMap<String, Vec<discovery::Candidate>>,
+) -> DoctorReport {
+    let system_path = system.read_system_path_registry().unwrap_or_default();
+    let user_path = system.read_user_path_registry().unwrap_or_default();
+    let entries = |value: &str| -> Vec<String> {
+        value.split(';').filter(|s| !s.is_empty()).map(str::to_string).collect()
+    };
+    let system_parts = entries(&system_path);
+    let user_parts = entries(&user_path);
+    let key = |entry: &str| normalize::canonicalize(entry, |name| system.lookup_env_var(name));
+
+    let mut duplicates = Vec::new();
+    let mut missing = Vec::new();
+    for (scope, parts) in [(Scope::System, &system_parts), (Scope::User, &user_parts)] {
+        let mut seen = HashSet::new();
+        for entry in parts {
+            if !seen.insert(key(entry)) {
+                duplicates.push(Entry { scope, entry: entry.clone() });
+            } else if !system.path_exists(Path::new(&system.expand_env_vars(entry))) {
+                missing.push(Entry { scope, entry: entry.clone() });
+            }
+        }
+    }
+
+    let system_keys: HashSet<String> = system_parts.iter().map(|e| key(e)).collect();
+    let overlaps: Vec<String> = user_parts.iter()
+        .filter(|e| system_keys.contains(&key(e)))
+        .cloned()
+        .collect();
+
+    let dirs = resolve::search_dirs(system);
+    let pathext = resolve::pathext(system);
+    let shims = discovery::chocolatey_shims(system);
+    let shadows: Vec<ShadowedCommand> = resolve::find_shadows(system, &dirs, candidates_map.keys(), &pathext)
+    .into_iter()
+    .map(|s| {
+        let winner = s.winner.file;
+        let shadowed: Vec<PathBuf> = s.shadowed.into_iter().map(|h| h.file).collect();
+        let targets = std::iter::once(&winner).chain(&shadowed)
+            .filter_map(|file| {
+                let target = shims.get(&normalize::canonicalize(&file.to_string_lossy(), |_| None))?;
+                Some((file.clone(), target.clone()))
+            })
+            .collect();
+        ShadowedCommand { command: s.command, winner, shadowed, targets }
+    })
+    .collect();
+
+    let index = resolve::DirIndex::new(system, &dirs);
+    let store_stubs: Vec<StoreStub> = ["python", "python3"].into_iter()
+        .filter_map(|command| {
+            let mut hits = index.hits(&dirs, command, &pathext).into_iter().map(|h| h.file);
+            let stub = hits.next().filter(|file| python::is_store_stub(system, file))?;
+            let shadowed = hits.filter(|file| !python::is_store_stub(system, file)).collect();
+            Some(StoreStub { command: command.to_string(), stub, shadowed })
+        })
+        .collect();
+
+    let in_javapath = |file: &PathBuf| file.parent().is_some_and(|dir| java::is_javapath(&key(&dir.to_string_lossy())));
+    let java = {
+        let mut hits = index.hits(&dirs, "java", &pathext).into_iter().map(|h| h.file);
+        hits.next().map(|java| {
+            let hidden_by_javapath = if in_javapath(&java) { hits.filter(|file| !in_javapath(file)).collect() } else { Vec::new() };
+            let java_home = discovery::environment_variable(system, "JAVA_HOME");
+            // The shim runs whatever Java the registry says, so it can't be compared
+            let java_home_matches = in_javapath(&java) || java_home.as_ref().is_none_or(|home| {
+                java.parent().is_some_and(|dir| key(&dir.to_string_lossy()) == key(&format!(r"{}\bin", home.trim_end_matches(['\\', '/']))))
+            });
+            JavaCheck { java, hidden_by_javapath, java_home, java_home_matches }
+        })
+    };
+
+    let length = budget::measure(system, &system_path, &user_path);
+
+    let mut report = DoctorReport {
+        system_entries: system_parts.len(),
+        user_entries: user_parts.len(),
+        session_entries: system.env_var("PATH").map(|p| p.split(';').filter(|s| !s.is_empty()).count()),
+        duplicates,
+        overlaps,
+        missing,
+        shadows,
+        store_stubs,
+        java,
+        length,
+        findings: Vec::new(),
+    };
+    report.findings = findings(&report);
+    report
+}
+
+/// Derives the coded findings from the collected sections.
+fn findings(report: &DoctorReport) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for d in &report.duplicates {
+        findings.push(Finding {
+            code: "WL001",
+            severity: Severity::Warning,
+            message: format!("Duplicate {:?} PATH entry: {}", d.scope, d.entry),
+        });
+    }
+    for entry in &report.overlaps {
+        findings.push(Finding {
+            code: "WL002",
+            severity: Severity::Warning,
+            message: format!("User PATH entry is already in the System PATH: {}", entry),
+        });
+    }
+    for m in &report.missing {
+        findings.push(Finding {
+            code: "WL003",
+            severity: Severity::Warning,
+            message: format!("{:?} PATH entry does not exist: {}", m.scope, m.entry),
+        });
+    }
+    for s in &report.shadows {
+        findings.push(Finding {
+            code: "WL004",
+            severity: Severity::Info,
+            message: format!("'{}' resolves to {} and shadows {} other copies", s.command, s.describe(&s.winner), s.shadowed.len()),
+        });
+    }
+    for stub in &report.store_stubs {
+        let (severity, message) = match stub.shadowed.first() {
+            Some(real) => (
+                Severity::Warning,
+                format!("'{}' opens the Microsoft Store instead of running {}. Turn off its App execution alias, or move the Python folder ahead of WindowsApps", stub.command, real.display()),
+            ),
+            None => (
+                Severity::Info,
+                format!("'{}' opens the Microsoft Store: no Python is installed on the PATH", stub.command),
+            ),
+        };
+        findings.push(Finding { code: "WL009", severity, message });
+    }
+    if let Some(check) = &report.java {
+        if let Some(jdk) = check.hidden_by_javapath.first() {
+            findings.push(Finding {
+                code: "WL010",
+                severity: Severity::Warning,
+                message: format!("'java' runs Oracle's javapath shim {} instead of {}. Run 'wanderlust heal' to put the chosen JDK ahead of it", check.java.display(), jdk.display()),
+            });
+        }
+        if let Some(home) = check.java_home.as_ref().filter(|_| !check.java_home_matches) {
+            findings.push(Finding {
+                code: "WL011",
+                severity: Severity::Info,
+                message: format!("JAVA_HOME is {}, but 'java' runs {}", home, check.java.display()),
+            });
+        }
+    }
+    let length = &report.length;
+    if length.is_truncated() {
+        findings.push(Finding {
+            code: "WL006",
+            severity: Severity::Error,
+            message: format!("Combined PATH is {} characters; Windows ignores everything past {}", length.combined, length.max),
+        });
+    } else if length.combined > length.legacy_limit {
+        findings.push(Finding {
+            code: "WL005",
+            severity: Severity::Warning,
+            message: format!("Combined PATH is {} characters; legacy tools truncate it at {}", length.combined, length.legacy_limit),
+        });
+    }
+    if length.approaching_max() {
+        findings.push(Finding {
+            code: "WL008",
+            severity: Severity::Warning,
+            message: format!("Combined PATH is {} characters, close to the {} Windows allows. Try 'wanderlust heal --compact'", length.combined, length.max),
+        });
+    } else if length.approaching_legacy_limit() {
+        findings.push(Finding {
+            code: "WL007",
+            severity: Severity::Info,
+            message: format!("Combined PATH is {} characters, close to the {} legacy tools allow", length.combined, length.legacy_limit),
+        });
+    }
+    findings
+}
+
+impl DoctorReport {
+    /// True if any finding is at least as severe as `threshold`.
+    pub fn has_findings_at(&self, threshold: Severity) -> bool {
+        self.findings.iter().any(|f| f.severity >= threshold)
+    }
+
+    /// Renders the report in `format`.
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_text(),
+            // Serializing plain data cannot fail
+            ReportFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default() + "\n",
+            ReportFormat::Markdown => self.to_markdown(),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let rule = "───────────────────────────────────────────────────────────────";
+        let system_dups = self.duplicates.iter().filter(|d| d.scope == Scope::System).count();
+        let user_dups = self.duplicates.len() - system_dups;
+        let mut out = String::new();
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "═══════════════════════════════════════════════════════════════");
+        let _ = writeln!(out, "                      PATH Health Report");
+        let _ = writeln!(out, "═══════════════════════════════════════════════════════════════");
+        let _ = writeln!(out);
+        let _ = writeln!(out, "Windows has TWO places where PATH is stored:");
+        let _ = writeln!(out);
+
+        // 1. System PATH (HKLM)
+        let _ = writeln!(out, "1. SYSTEM PATH ({} folders)", self.system_entries);
+        let _ = writeln!(out, "   Shared by all users. Has Windows, Program Files, etc.");
+        if system_dups > 0 {
+            let _ = writeln!(out, "   ⚠ Problem: {} duplicate entries (run as Admin to fix)", system_dups);
+        } else {
+            let _ = writeln!(out, "   ✓ No duplicates");
+        }
+
+        // 2. User PATH (HKCU)
+        let _ = writeln!(out);
+        let _ = writeln!(out, "2. USER PATH ({} folders)", self.user_entries);
+        let _ = writeln!(out, "   Just for you. Has your tools like Python, Cargo, Scoop, etc.");
+        if user_dups > 0 {
+            let _ = writeln!(out, "   ⚠ Problem: {} duplicate entries", user_dups);
+        } else {
+            let _ = writeln!(out, "   ✓ No duplicates");
+        }
+
+        // 3. User entries that duplicate System entries
+        if !self.overlaps.is_empty() {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "⚠ OVERLAP: {} folders appear in BOTH System and User PATH.", self.overlaps.len());
+            let _ = writeln!(out, "   This is wasteful. Examples:");
+            for p in self.overlaps.iter().take(3) {
+                let _ = writeln!(out, "     - {}", p);
+            }
+            if self.overlaps.len() > 3 {
+                let _ = writeln!(out, "     ... and {} more", self.overlaps.len() - 3);
+            }
+        }
+
+        // 4. Folders that no longer exist
+        if !self.missing.is_empty() {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "⚠ MISSING: {} folders in your PATH do not exist.", self.missing.len());
+            for m in &self.missing {
+                let _ = writeln!(out, "     - {}  ({:?} PATH)", m.entry, m.scope);
+            }
+        }
+
+        // 5. Commands that exist more than once: only the first one found ever runs
+        let _ = writeln!(out);
+        if self.shadows.is_empty() {
+            let _ = writeln!(out, "✓ No shadowed commands: every command resolves to exactly one file.");
+        } else {
+            let _ = writeln!(out, "ℹ SHADOWED: {} commands exist in more than one PATH folder.", self.shadows.len());
+            let _ = writeln!(out, "   Windows runs the first one (System PATH first, then User PATH, then PATHEXT order).");
+            for shadow in &self.shadows {
+                let _ = writeln!(out);
+                let _ = writeln!(out, "   {}", shadow.command);
+                let _ = writeln!(out, "     ✓ {}", shadow.describe(&shadow.winner));
+                for file in &shadow.shadowed {
+                    let _ = writeln!(out, "     ✕ {}  (never runs)", shadow.describe(file));
+                }
+            }
+            let _ = writeln!(out);
+            let _ = writeln!(out, "   To change which one wins, reorder your PATH or 'pin' the right folder in config.toml.");
+        }
+
+        for stub in &self.store_stubs {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "⚠ STORE STUB: '{}' resolves to {}, which only opens the Microsoft Store.", stub.command, stub.stub.display());
+            for file in &stub.shadowed {
+                let _ = writeln!(out, "     ✕ {}  (never runs)", file.display());
+            }
+        }
+
+        if let Some(check) = &self.java {
+            if !check.hidden_by_javapath.is_empty() {
+                let _ = writeln!(out);
+                let _ = writeln!(out, "⚠ JAVAPATH: 'java' resolves to {}, Oracle's javapath shim.", check.java.display());
+                for file in &check.hidden_by_javapath {
+                    let _ = writeln!(out, "     ✕ {}  (never runs)", file.display());
+                }
+            }
+            if let Some(home) = check.java_home.as_ref().filter(|_| !check.java_home_matches) {
+                let _ = writeln!(out);
+                let _ = writeln!(out, "ℹ JAVA_HOME: {} is not where 'java' comes from ({}).", home, check.java.display());
+                let _ = writeln!(out, "   Build tools (Maven, Gradle) use JAVA_HOME; set 'set_java_home' under [java] in config.toml to align it.");
+            }
+        }
+
+        // 6. Current terminal session explanation
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{}", rule);
+        let _ = writeln!(out);
+        let total = self.system_entries + self.user_entries;
+        let _ = writeln!(out, "When you open a terminal, Windows combines both:");
+        let _ = writeln!(out, "  System ({}) + User ({}) = {} folders to search for commands",
+                         self.system_entries, self.user_entries, total);
+        let _ = writeln!(out, "  That is {} characters, {} as stored (legacy tools stop at {}, Windows at {}).",
+                         self.length.combined, self.length.combined_unexpanded, self.length.legacy_limit, self.length.max);
+        if let Some(current) = self.session_entries.filter(|&n| n != total) {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "  Your current terminal has {} (Git Bash adds some extras).", current);
+        }
+
+        // 7. Summary
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{}", rule);
+        let _ = writeln!(out);
+        if self.has_findings_at(Severity::Warning) {
+            for f in self.findings.iter().filter(|f| f.severity >= Severity::Warning) {
+                let _ = writeln!(out, "{} {}: {}", severity_icon(f.severity), f.code, f.message);
+            }
+            let _ = writeln!(out);
+            let _ = writeln!(out, "Run 'wanderlust heal' to fix the issues above.");
+        } else {
+            let _ = writeln!(out, "✓ Your PATH is healthy! No action needed.");
+        }
+        let _ = writeln!(out);
+        out
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# PATH Health Report");
+        let _ = writeln!(out);
+        let _ = writeln!(out, "| | Folders | Characters |");
+        let _ = writeln!(out, "|---|---:|---:|");
+        let _ = writeln!(out, "| System PATH | {} | {} |", self.system_entries, self.length.system);
+        let _ = writeln!(out, "| User PATH | {} | {} |", self.user_entries, self.length.user);
+        let _ = writeln!(out, "| Combined (as stored) | {} | {} |", self.system_entries + self.user_entries, self.length.combined_unexpanded);
+        let _ = writeln!(out, "| Combined (expanded) | {} | {} |", self.system_entries + self.user_entries, self.length.combined);
+        let _ = writeln!(out);
+        let _ = writeln!(out, "## Findings");
+        let _ = writeln!(out);
+        if self.findings.is_empty() {
+            let _ = writeln!(out, "No findings. The PATH is healthy.");
+        } else {
+            let _ = writeln!(out, "| Severity | Code | Message |");
+            let _ = writeln!(out, "|---|---|---|");
+            for f in &self.findings {
+                let _ = writeln!(out, "| {:?} | {} | {} |", f.severity, f.code, markdown_cell(&f.message));
+            }
+        }
+        if !self.shadows.is_empty() {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "## Shadowed commands");
+            for s in &self.shadows {
+                let _ = writeln!(out);
+                let _ = writeln!(out, "- `{}` runs `{}`", s.command, s.winner.display());
+                if let Some(target) = s.targets.get(&s.winner) {
+                    let _ = writeln!(out, "  - a shim for `{}`", target.display());
+                }
+                for file in &s.shadowed {
+                    let _ = writeln!(out, "  - shadows `{}`", file.display());
+                }
+            }
+        }
+        out
+    }
+}
+
+fn severity_icon(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "ℹ",
+        Severity::Warning => "⚠",
+        Severity::Error => "✕",
+    }
+}
+
+/// Escapes `|` so a message cannot break a Markdown table row.
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::{LEGACY_PATH_LIMIT, MAX_PATH_LENGTH};
+    use crate::system::MockSystem;
+
+    fn fixture() -> MockSystem {
+        let system = MockSystem::new();
+        system.add_dir("/sys/bin");
+        system.add_dir("/home/me/tools");
+        system.add_file("/sys/bin/python.exe");
+        system.add_file("/home/me/tools/python.exe");
+        system.set_env("TOOLS", "/home/me/tools");
+        system.write_system_path_registry("/sys/bin;/sys/bin/").unwrap();
+        system.write_user_path_registry("%TOOLS%;/sys/bin;/gone").unwrap();
+        system
+    }
+
+    fn commands(names: &[&str]) -> HashMap<String, Vec<discovery::Candidate>> {
+        names.iter().map(|n| (n.to_string(), Vec::new())).collect()
+    }
+
+    #[test]
+    fn test_build_report_sections_and_codes() {
+        let report = build_report(&fixture(), &commands(&["python"]));
+
+        assert_eq!(report.duplicates, vec![Entry { scope: Scope::System, entry: "/sys/bin/".to_string() }]);
+        assert_eq!(report.overlaps, vec!["/sys/bin".to_string()]);
+        assert_eq!(report.missing, vec![Entry { scope: Scope::User, entry: "/gone".to_string() }]);
+        assert_eq!(report.shadows[0].winner, PathBuf::from("/sys/bin/python.exe"));
+        assert_eq!(report.length.combined, "/sys/bin;/sys/bin/;/home/me/tools;/sys/bin;/gone".len());
+
+        let codes: Vec<&str> = report.findings.iter().map(|f| f.code).collect();
+        assert_eq!(codes, vec!["WL001", "WL002", "WL003", "WL004"]);
+        assert!(report.has_findings_at(Severity::Warning));
+        assert!(!report.has_findings_at(Severity::Error));
+    }
+
+    #[test]
+    fn test_shadowing_reports_shim_targets() {
+        let system = fixture();
+        system.set_env("ChocolateyInstall", "/choco");
+        system.add_file("/choco/bin/python.exe");
+        system.add_file("/choco/lib/python3/tools/python.exe");
+        system.write_system_path_registry("/choco/bin;/sys/bin").unwrap();
+
+        let report = build_report(&system, &commands(&["python"]));
+
+        let shadow = &report.shadows[0];
+        assert_eq!(shadow.winner, PathBuf::from("/choco/bin/python.exe"));
+        assert_eq!(shadow.targets.get(&shadow.winner), Some(&PathBuf::from("/choco/lib/python3/tools/python.exe")));
+        assert_eq!(shadow.targets.len(), 1, "The other copies are not shims");
+        assert!(report.render(ReportFormat::Text).contains("/choco/bin/python.exe (shim for /choco/lib/python3/tools/python.exe)"));
+    }
+
+    #[test]
+    fn test_python_store_stub_is_flagged() {
+        let system = MockSystem::new();
+        system.add_file("/home/me/AppData/Local/Microsoft/WindowsApps/python.exe");
+        system.add_file("/home/me/AppData/Local/Microsoft/WindowsApps/python3.exe");
+        system.add_file("/home/me/Python312/python.exe");
+        system.write_user_path_registry("/home/me/AppData/Local/Microsoft/WindowsApps;/home/me/Python312").unwrap();
+
+        let report = build_report(&system, &HashMap::new());
+
+        let stubs: Vec<(&str, usize)> = report.store_stubs.iter().map(|s| (s.command.as_str(), s.shadowed.len())).collect();
+        assert_eq!(stubs, vec![("python", 1), ("python3", 0)]);
+        let severities: Vec<Severity> = report.findings.iter().filter(|f| f.code == "WL009").map(|f| f.severity).collect();
+        assert_eq!(severities, vec![Severity::Warning, Severity::Info]);
+    }
+
+    #[test]
+    fn test_javapath_and_java_home_findings() {
+        let system = MockSystem::new();
+        system.add_file("/ProgramData/Oracle/Java/javapath/java.exe");
+        system.add_file("/jdk-21/bin/java.exe");
+        system.add_file("/jdk-17/bin/java.exe");
+        system.write_system_path_registry("/ProgramData/Oracle/Java/javapath;/jdk-21/bin").unwrap();
+        system.set_env("JAVA_HOME", "/jdk-17");
+
+        let report = build_report(&system, &HashMap::new());
+        let codes: Vec<&str> = report.findings.iter().map(|f| f.code).collect();
+        assert_eq!(codes, vec!["WL010"], "JAVA_HOME can't be compared with the shim");
+        assert_eq!(report.java.unwrap().hidden_by_javapath, vec![PathBuf::from("/jdk-21/bin/java.exe")]);
+
+        system.write_system_path_registry("/jdk-21/bin;/ProgramData/Oracle/Java/javapath").unwrap();
+        let report = build_report(&system, &HashMap::new());
+        let codes: Vec<&str> = report.findings.iter().map(|f| f.code).collect();
+        assert_eq!(codes, vec!["WL011"]);
+
+        system.set_env("JAVA_HOME", "/jdk-21/");
+        assert!(build_report(&system, &HashMap::new()).findings.is_empty());
+    }
+
+    #[test]
+    fn test_length_budget_findings() {
+        let system = MockSystem::new();
+        system.write_user_path_registry(&"x".repeat(LEGACY_PATH_LIMIT + 1)).unwrap();
+        let report = build_report(&system, &HashMap::new());
+        assert!(report.findings.iter().any(|f| f.code == "WL005" && f.severity == Severity::Warning));
+
+        system.write_user_path_registry(&"x".repeat(MAX_PATH_LENGTH + 1)).unwrap();
+        let report = build_report(&system, &HashMap::new());
+        assert!(report.findings.iter().any(|f| f.code == "WL006"));
+        assert!(report.has_findings_at(Severity::Error));
+
+        system.write_user_path_registry(&"x".repeat(LEGACY_PATH_LIMIT - 100)).unwrap();
+        let codes: Vec<&str> = build_report(&system, &HashMap::new()).findings.iter().map(|f| f.code).filter(|c| *c != "WL003").collect();
+        assert_eq!(codes, vec!["WL007"]);
+
+        system.write_user_path_registry(&"x".repeat(MAX_PATH_LENGTH - 100)).unwrap();
+        let codes: Vec<&str> = build_report(&system, &HashMap::new()).findings.iter().map(|f| f.code).filter(|c| *c != "WL003").collect();
+        assert_eq!(codes, vec!["WL005", "WL008"]);
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let report = build_report(&fixture(), &commands(&["python"]));
+
+        let json: serde_json::Value = serde_json::from_str(&report.render(ReportFormat::Json)).unwrap();
+        assert_eq!(json["findings"][0]["code"], "WL001");
+        assert_eq!(json["findings"][0]["severity"], "warning");
+        assert_eq!(json["missing"][0]["scope"], "user");
+
+        let markdown = report.render(ReportFormat::Markdown);
+        assert!(markdown.contains("| Warning | WL003 | User PATH entry does not exist: /gone |"), "{}", markdown);
+
+        let text = report.render(ReportFormat::Text);
+        assert!(text.contains("⚠ WL002"), "{}", text);
+        assert!(text.contains("Run 'wanderlust heal'"));
+    }
+}
@@ -0,0 +1,271 @@
+//! # JDK Discovery
+//!
+//! Every Java vendor registers its installs differently:
+//!
+//! | Vendor    | Registry key (HKLM)                                  | Value             |
+//! |-----------|------------------------------------------------------|-------------------|
+//! | Oracle    | `SOFTWARE\JavaSoft\JDK\<version>`                    | `JavaHome`        |
+//! | Adoptium  | `SOFTWARE\Eclipse Adoptium\JDK\<version>\hotspot\MSI` | `Path`            |
+//! | Microsoft | `SOFTWARE\Microsoft\JDK\<version>\hotspot\MSI`       | `Path`            |
+//! | Azul Zulu | `SOFTWARE\Azul Systems\Zulu\<name>`                  | `InstallationPath` |
+//!
+//! Anything else is found through its Uninstall record. `installed_jdks` merges them all,
+//! and only the one chosen by `config::JavaDefault` gets its `bin` onto the PATH; the
+//! `bin` directories of the others are dropped from discovery.
+//!
+//! Oracle's installers also put `javapath`, a directory of `java.exe` shims for the most
+//! recently installed Oracle runtime, into the System PATH. Since the System PATH is
+//! searched first, `cleaner::plan_healing` puts the chosen `bin` right in front of it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use log::{debug, warn};
+use crate::config::JavaDefault;
+use crate::discovery::{self, Candidate};
+use crate::normalize;
+use crate::system::{DiscoveryOps, Hive};
+
+/// The discovery source label of the chosen JDK's `bin`.
+pub const SOURCE: &str = "jdk";
+
+const UNINSTALL_KEY: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall";
+
+/// Vendor registrations: `(vendor, key with one subkey per version, subkey below it, value)`.
+const VENDOR_KEYS: &[(&str, &str, &str, &str)] = &[
+    ("Oracle", r"SOFTWARE\JavaSoft\JDK", "", "JavaHome"),
+    ("Oracle", r"SOFTWARE\JavaSoft\Java Development Kit", "", "JavaHome"),
+    ("Adoptium", r"SOFTWARE\Eclipse Adoptium\JDK", r"hotspot\MSI", "Path"),
+    ("AdoptOpenJDK", r"SOFTWARE\AdoptOpenJDK\JDK", r"hotspot\MSI", "Path"),
+    ("Microsoft", r"SOFTWARE\Microsoft\JDK", r"hotspot\MSI", "Path"),
+    ("Zulu", r"SOFTWARE\Azul Systems\Zulu", "", "InstallationPath"),
+];
+
+/// An installed JDK.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jdk {
+    pub vendor: String,
+    /// The version as registered, e.g. `21.0.2` or `1.8.0_392`.
+    pub version: String,
+    /// The directory `JAVA_HOME` should point at; `java.exe` is in its `bin`.
+    pub home: PathBuf,
+}
+
+impl Jdk {
+    /// The feature release: `21.0.2` -> 21, and the old `1.8.0_392` -> 8.
+    pub fn major(&self) -> u32 {
+        major(&self.version)
+    }
+
+    pub fn bin(&self) -> PathBuf {
+        self.home.join("bin")
+    }
+}
+
+fn major(version: &str) -> u32 {
+    let mut numbers = version.split(|c: char| !c.is_ascii_digit()).filter(|s| !s.is_empty());
+    match numbers.next().and_then(|n| n.parse().ok()) {
+        Some(1) => numbers.next().and_then(|n| n.parse().ok()).unwrap_or(1),
+        Some(n) => n,
+        None => 0,
+    }
+}
+
+/// The numeric components of a version, for comparing two builds of the same major release.
+fn version_key(version: &str) -> Vec<u32> {
+    version.split(|c: char| !c.is_ascii_digit()).filter_map(|s| s.parse().ok()).collect()
+}
+
+/// Every JDK installed, from the vendor registrations first and then the Uninstall records,
+/// each home once. Only directories with a `bin\java.exe` count.
+pub fn installed_jdks(system: &impl DiscoveryOps) -> Vec<Jdk> {
+    let mut found: Vec<Jdk> = Vec::new();
+    let mut add = |vendor: &str, version: String, home: &str| {
+        let home = PathBuf::from(home.trim().trim_end_matches('\\'));
+        if home.as_os_str().is_empty() || !system.is_file(&home.join("bin").join("java.exe")) {
+            return;
+        }
+        let key = normalize::canonicalize(&home.to_string_lossy(), |_| None);
+        match found.iter_mut().find(|j| normalize::canonicalize(&j.home.to_string_lossy(), |_| None) == key) {
+            // `JavaSoft\JDK\21` and `JavaSoft\JDK\21.0.2` describe the same install
+            Some(existing) => {
+                if version.len() > existing.version.len() {
+                    existing.version = version;
+                }
+            }
+            None => found.push(Jdk { vendor: vendor.to_string(), version, home }),
+        }
+    };
+
+    for &(vendor, key, below, value) in VENDOR_KEYS {
+        for version in system.registry_subkeys(Hive::LocalMachine, key) {
+            let path = if below.is_empty() {
+                format!(r"{}\{}", key, version)
+            } else {
+                format!(r"{}\{}\{}", key, version, below)
+            };
+            if let Some(home) = system.registry_string(Hive::LocalMachine, &path, value) {
+                // Zulu keys are named `zulu-21`; the version is a value
+                let version = system.registry_string(Hive::LocalMachine, &path, "CurrentVersion")
+                    .unwrap_or_else(|| version.trim_start_matches("zulu-").to_string());
+                add(vendor, version, &home);
+            }
+        }
+    }
+
+    for hive in [Hive::LocalMachine, Hive::CurrentUser] {
+        for subkey in system.registry_subkeys(hive, UNINSTALL_KEY) {
+            let path = format!(r"{}\{}", UNINSTALL_KEY, subkey);
+            let Some(home) = system.registry_string(hive, &path, "InstallLocation") else {
+                continue;
+            };
+            if !system.is_file(&Path::new(home.trim_end_matches('\\')).join("bin").join("javac.exe")) {
+                continue;
+            }
+            let vendor = system.registry_string(hive, &path, "Publisher").unwrap_or_else(|| "Unknown".to_string());
+            let version = system.registry_string(hive, &path, "DisplayVersion").unwrap_or_default();
+            add(&vendor, version, &home);
+        }
+    }
+    found
+}
+
+/// The JDK `policy` picks from `jdks`. `java_home` is the current `%JAVA_HOME%`, which `Auto`
+/// keeps if it points at one of them.
+pub fn select<'a>(jdks: &'a [Jdk], policy: &JavaDefault, java_home: Option<&str>) -> Option<&'a Jdk> {
+    let newest = |wanted: Option<u32>| {
+        jdks.iter()
+            .filter(|j| wanted.is_none_or(|m| j.major() == m))
+            .max_by_key(|j| (j.major(), version_key(&j.version)))
+    };
+    match policy {
+        JavaDefault::Auto => {
+            let current = java_home.map(|home| normalize::canonicalize(home, |_| None));
+            jdks.iter()
+                .find(|j| Some(normalize::canonicalize(&j.home.to_string_lossy(), |_| None)) == current)
+                .or_else(|| newest(None))
+        }
+        JavaDefault::Newest => newest(None),
+        JavaDefault::Version(major) => {
+            let chosen = newest(Some(*major));
+            if chosen.is_none() && !jdks.is_empty() {
+                warn!("No JDK {} is installed; not adding a JDK", major);
+            }
+            chosen
+        }
+        JavaDefault::None => None,
+    }
+}
+
+/// The JDK chosen by `policy` on `system`.
+pub fn chosen_jdk(system: &impl DiscoveryOps, policy: &JavaDefault) -> Option<Jdk> {
+    let jdks = installed_jdks(system);
+    let java_home = discovery::environment_variable(system, "JAVA_HOME");
+    select(&jdks, policy, java_home.as_deref()).cloned()
+}
+
+/// Adds the chosen JDK's `bin` to `map` under `SOURCE`, and drops the `bin` directories of
+/// every other installed JDK that other sources (usually the Uninstall scan) found.
+pub(crate) fn scan(system: &impl DiscoveryOps, policy: &JavaDefault, map: &mut HashMap<String, Vec<Candidate>>) {
+    let jdks = installed_jdks(system);
+    let java_home = discovery::environment_variable(system, "JAVA_HOME");
+    let chosen = select(&jdks, policy, java_home.as_deref());
+    let key = |dir: &Path| normalize::canonicalize(&dir.to_string_lossy(), |_| None);
+
+    let others: Vec<String> = jdks.iter()
+        .filter(|j| Some(*j) != chosen)
+        .flat_map(|j| [key(&j.bin()), key(&j.home)])
+        .collect();
+    for candidates in map.values_mut() {
+        candidates.retain(|c| {
+            let other = others.contains(&key(&c.path));
+            if other {
+                debug!("Skipping {:?} ({}): not the chosen JDK", c.path, c.source);
+            }
+            !other
+        });
+    }
+    map.retain(|_, candidates| !candidates.is_empty());
+
+    if let Some(jdk) = chosen {
+        debug!("Default JDK: {} {} in {:?}", jdk.vendor, jdk.version, jdk.home);
+        discovery::add_dir_candidates(system, map, &jdk.bin(), SOURCE);
+    }
+}
+
+/// Is the entry with canonical key `key` Oracle's `javapath` shim directory
+/// (`C:\Program Files\Common Files\Oracle\Java\javapath`, or under `ProgramData` for Java 8)?
+pub fn is_javapath(key: &str) -> bool {
+    key.ends_with(r"\oracle\java\javapath")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    fn fixture() -> MockSystem {
+        let system = MockSystem::new();
+        let jdk = |key: &str, value: &str, home: &str| {
+            system.set_registry_string(Hive::LocalMachine, key, value, home);
+            system.add_file(format!("{}/bin/java.exe", home));
+            system.add_file(format!("{}/bin/javac.exe", home));
+        };
+        jdk(r"SOFTWARE\JavaSoft\JDK\17", "JavaHome", "/opt/jdk-17");
+        jdk(r"SOFTWARE\JavaSoft\JDK\17.0.10", "JavaHome", "/opt/jdk-17");
+        jdk(r"SOFTWARE\Eclipse Adoptium\JDK\21.0.2.13\hotspot\MSI", "Path", "/opt/temurin-21");
+        jdk(r"SOFTWARE\Microsoft\JDK\11.0.22.7\hotspot\MSI", "Path", "/opt/msjdk-11");
+        jdk(r"SOFTWARE\Azul Systems\Zulu\zulu-8", "InstallationPath", "/opt/zulu-8");
+        system.set_registry_string(Hive::LocalMachine, r"SOFTWARE\Azul Systems\Zulu\zulu-8", "CurrentVersion", "1.8.0_402");
+        let uninstall = format!(r"{}\Corretto", UNINSTALL_KEY);
+        system.set_registry_string(Hive::LocalMachine, &uninstall, "InstallLocation", "/opt/corretto-21");
+        system.set_registry_string(Hive::LocalMachine, &uninstall, "DisplayVersion", "21.0.1");
+        system.add_file("/opt/corretto-21/bin/java.exe");
+        system.add_file("/opt/corretto-21/bin/javac.exe");
+        system
+    }
+
+    #[test]
+    fn test_installed_jdks_from_every_vendor() {
+        let jdks = installed_jdks(&fixture());
+        let found: Vec<(&str, &str, u32)> = jdks.iter().map(|j| (j.vendor.as_str(), j.version.as_str(), j.major())).collect();
+
+        assert_eq!(found, vec![
+            ("Oracle", "17.0.10", 17),
+            ("Adoptium", "21.0.2.13", 21),
+            ("Microsoft", "11.0.22.7", 11),
+            ("Zulu", "1.8.0_402", 8),
+            ("Unknown", "21.0.1", 21),
+        ]);
+    }
+
+    #[test]
+    fn test_select_by_policy() {
+        let jdks = installed_jdks(&fixture());
+        let home = |jdk: Option<&Jdk>| jdk.map(|j| j.home.to_string_lossy().to_string());
+
+        assert_eq!(home(select(&jdks, &JavaDefault::Newest, None)).as_deref(), Some("/opt/temurin-21"), "21.0.2 beats 21.0.1");
+        assert_eq!(home(select(&jdks, &JavaDefault::Version(8), None)).as_deref(), Some("/opt/zulu-8"));
+        assert_eq!(home(select(&jdks, &JavaDefault::Auto, Some("/opt/msjdk-11/"))).as_deref(), Some("/opt/msjdk-11"), "JAVA_HOME wins");
+        assert_eq!(home(select(&jdks, &JavaDefault::Auto, Some("/gone"))).as_deref(), Some("/opt/temurin-21"));
+        assert_eq!(select(&jdks, &JavaDefault::Version(22), None), None);
+        assert_eq!(select(&jdks, &JavaDefault::None, None), None);
+    }
+
+    #[test]
+    fn test_scan_keeps_only_the_chosen_bin() {
+        let system = fixture();
+        let mut map: HashMap<String, Vec<Candidate>> = HashMap::new();
+        for home in ["/opt/jdk-17", "/opt/corretto-21"] {
+            discovery::add_dir_candidates(&system, &mut map, &Path::new(home).join("bin"), "HKLM_Uninstall");
+        }
+
+        scan(&system, &JavaDefault::Version(17), &mut map);
+
+        let java: Vec<(PathBuf, String)> = map["java"].iter().map(|c| (c.path.clone(), c.source.clone())).collect();
+        assert_eq!(java, vec![
+            (PathBuf::from("/opt/jdk-17/bin"), "HKLM_Uninstall".to_string()),
+            (PathBuf::from("/opt/jdk-17/bin"), SOURCE.to_string()),
+        ]);
+        assert!(is_javapath(&normalize::canonicalize(r"C:\Program Files\Common Files\Oracle\Java\javapath", |_| None)));
+    }
+}
@@ -1,5 +1,19 @@
+pub mod backup;
+pub mod budget;
 pub mod cleaner;
+pub mod config;
 pub mod discovery;
+pub mod doctor;
+#[cfg(windows)]
 pub mod elevation;
 pub mod invariant_ppt;
+pub mod java;
+pub mod logging;
+pub mod normalize;
+pub mod plan;
+pub mod python;
+pub mod rebalance;
+pub mod resolve;
+pub mod state;
 pub mod system;
+pub mod tool_homes;
@@ -0,0 +1,12 @@
+//! # Logging
+//!
+//! Log messages go to stderr, never stdout: `doctor --format json` and `--format markdown`
+//! print a report on stdout that scripts parse, and a config warning must not end up in it.
+
+use log::LevelFilter;
+use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
+
+/// Installs the logger for the whole process. Failing to do so is not worth crashing over.
+pub fn init(level: LevelFilter) {
+    let _ = TermLogger::init(level, Config::default(), TerminalMode::Stderr, ColorChoice::Auto);
+}
@@ -6,10 +6,17 @@
 //!
 //! The application is designed to be run as an Administrator (for `heal`, `install`, `uninstall`).
 
+use std::path::PathBuf;
 use clap::{Parser, Subcommand};
-use log::{info, error, warn, LevelFilter};
-use simplelog::{Config, SimpleLogger};
-use wanderlust::{cleaner, elevation};
+use log::{error, LevelFilter};
+#[cfg(windows)]
+use log::{info, warn};
+use wanderlust::config::PathOrder;
+use wanderlust::doctor::{ReportFormat, Severity};
+#[cfg(windows)]
+use wanderlust::{backup, cleaner, config, discovery, doctor, elevation, resolve};
+#[cfg(windows)]
+use wanderlust::system::WindowsSystem;
 
 /// The primary Command Line Interface (CLI) configuration.
 ///
@@ -32,6 +39,7 @@ struct Cli {
 
 /// Available sub-commands for the Wanderlust utility.
 #[derive(Subcommand)]
+#[cfg_attr(not(windows), allow(dead_code))]
 enum Commands {
     /// Analyze and fix the PATH once.
     ///
@@ -46,6 +54,39 @@ enum Commands {
         /// Useful for auditing what Wanderlust *would* do without risk.
         #[arg(long)]
         dry_run: bool,
+
+        /// Write the plan to this JSON file instead of applying it.
+        ///
+        /// Review the file, then run `wanderlust apply <FILE>` to apply exactly that plan.
+        #[arg(long, value_name = "FILE")]
+        plan_out: Option<PathBuf>,
+
+        /// How to order the rebuilt User PATH. Overrides `order` in the config file.
+        ///
+        /// `preserve` (the default) keeps your current order and appends new directories best
+        /// discovery source first (see `source_priority`), then alphabetically.
+        #[arg(long, value_enum)]
+        order: Option<PathOrder>,
+
+        /// Shorten the User PATH if the combined PATH is over a length limit (2047 or 32767 characters).
+        ///
+        /// Uses `%VAR%` forms such as `%LOCALAPPDATA%\...` first, then drops the last unpinned entries.
+        /// Same as `compact = true` in the config file.
+        #[arg(long)]
+        compact: bool,
+
+        /// Move per-user folders out of the System PATH and machine-wide ones out of the User PATH.
+        ///
+        /// Needs Administrator for the System PATH. Same as `rebalance = true` in the config file.
+        #[arg(long)]
+        rebalance: bool,
+    },
+    /// Apply a plan previously written by `heal --plan-out`.
+    ///
+    /// Refuses if the User or System PATH has changed since the plan was generated.
+    Apply {
+        /// The reviewed plan file.
+        plan: PathBuf,
     },
     /// Inspect the PATH and report issues.
     ///
@@ -53,7 +94,45 @@ enum Commands {
     /// - Duplicate entries.
     /// - Broken paths (directories that don't exist).
     /// - Shadowed commands.
-    Doctor,
+    /// - PATH length against the 2047 / 32767 character limits.
+    ///
+    /// Exit codes: 0 if no finding reaches `--fail-on`, 1 if one does.
+    Doctor {
+        /// Output format. `json` and `markdown` are meant for scripts and reports.
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+
+        /// Exit with code 1 if any finding is at least this severe.
+        #[arg(long, value_enum, default_value_t = Severity::Error)]
+        fail_on: Severity,
+    },
+    /// Explain how a command name resolves: every match in Windows lookup order, winner marked.
+    ///
+    /// Searches the current directory, the System PATH then User PATH (in `PATHEXT` order),
+    /// and `App Paths`, and lists discovered copies that are not on the PATH.
+    Which {
+        /// The command as you would type it (e.g. `python` or `python.exe`).
+        command: String,
+    },
+    /// Inspect the PATH backup history.
+    ///
+    /// A backup is written to `%LOCALAPPDATA%\wanderlust\backups` before every PATH change.
+    Backups {
+        #[command(subcommand)]
+        command: BackupCommands,
+    },
+    /// Restore a PATH from the backup history and broadcast the change.
+    ///
+    /// The PATH being replaced is backed up first, so a restore can be undone.
+    Restore {
+        /// The backup id, as shown by `wanderlust backups list`.
+        id: String,
+    },
+    /// Inspect the config file (`%APPDATA%\wanderlust\config.toml`).
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
     /// Install as a scheduled task (runs every 30 minutes).
     ///
     /// This creates a Windows Scheduled Task running with highest privileges.
@@ -64,6 +143,30 @@ enum Commands {
     Uninstall,
 }
 
+/// Sub-commands of `wanderlust backups`.
+#[derive(Subcommand)]
+#[cfg_attr(not(windows), allow(dead_code))]
+enum BackupCommands {
+    /// List all stored backups, oldest first.
+    List,
+    /// Print the PATH stored in one backup.
+    Show {
+        /// The backup id, as shown by `wanderlust backups list`.
+        id: String,
+    },
+}
+
+/// Sub-commands of `wanderlust config`.
+#[derive(Subcommand)]
+#[cfg_attr(not(windows), allow(dead_code))]
+enum ConfigCommands {
+    /// Parse the config file and report unknown keys, invalid globs and unknown sources.
+    Validate {
+        /// The config file to check, instead of the default location.
+        file: Option<PathBuf>,
+    },
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -74,24 +177,109 @@ fn main() {
         _ => LevelFilter::Trace,
     };
 
-    // Initialize logger
-    // We ignore the result here as logging failure shouldn't crash the startup
-    let _ = SimpleLogger::init(log_level, Config::default());
+    // Initialize logger (on stderr, so reports on stdout stay parseable)
+    wanderlust::logging::init(log_level);
+
+    run(&cli);
+}
 
+/// Non-Windows builds exist so the library logic can be tested anywhere,
+/// but there is no PATH registry to heal, so every command is refused.
+#[cfg(not(windows))]
+fn run(cli: &Cli) {
+    if cli.command.is_none() {
+        use clap::CommandFactory;
+        let _ = Cli::command().print_help();
+        return;
+    }
+    error!("Wanderlust manages the Windows PATH and only runs on Windows.");
+    std::process::exit(1);
+}
+
+/// Dispatches the parsed sub-command.
+#[cfg(windows)]
+fn run(cli: &Cli) {
     match &cli.command {
-        Some(Commands::Heal { dry_run }) => {
+        Some(Commands::Heal { dry_run, plan_out, order, compact, rebalance }) => {
             // User PATH (HKCU) does NOT require elevation - normal user can write to it
-            // System PATH (HKLM) requires Admin, but we handle that gracefully in clean_system_path
+            // System PATH (HKLM) requires Admin, but we handle that gracefully in apply_plan
             // So we just run directly - no elevation needed for the common case
             
-            if let Err(e) = cleaner::heal_path(*dry_run) {
+            // A broken config must not be ignored: it may be what keeps us off the System PATH
+            let mut config = match load_config() {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("{:#}. Run 'wanderlust config validate' for details.", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(order) = order {
+                config.order = *order;
+            }
+            config.compact |= *compact;
+            config.rebalance |= *rebalance;
+            
+            if let Err(e) = cleaner::heal_path(*dry_run, plan_out.as_deref(), &config) {
                 error!("Failed to heal PATH: {}", e);
                 std::process::exit(1);
             }
         }
-        Some(Commands::Doctor) => {
-            if let Err(e) = cleaner::doctor() {
-                error!("Doctor check failed: {}", e);
+        Some(Commands::Apply { plan }) => {
+            if let Err(e) = cleaner::apply_plan_file(plan) {
+                error!("Failed to apply plan: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Doctor { format, fail_on }) => {
+            let config = load_config_for_reading();
+            let report = doctor::doctor(&config, *format);
+            if report.has_findings_at(*fail_on) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Which { command }) => {
+            let config = load_config_for_reading();
+            let candidates_map = discovery::discover_candidates(&WindowsSystem, &config);
+            let cwd = std::env::current_dir().ok();
+            if !resolve::print_which(&WindowsSystem, cwd.as_deref(), &candidates_map, command) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Backups { command }) => match command {
+            BackupCommands::List => backup::print_backup_list(&WindowsSystem),
+            BackupCommands::Show { id } => {
+                if let Err(e) = backup::print_backup(&WindowsSystem, id) {
+                    error!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Some(Commands::Restore { id }) => {
+            if let Err(e) = backup::restore_backup(&WindowsSystem, id) {
+                error!("Failed to restore PATH: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Config { command: ConfigCommands::Validate { file } }) => {
+            let Some(path) = file.clone().or_else(config::config_path) else {
+                error!("Could not determine the config file location");
+                std::process::exit(1);
+            };
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) => {
+                    error!("Failed to read {:?}: {}", path, e);
+                    std::process::exit(1);
+                }
+            };
+            let problems = config::validate(&text);
+            if problems.is_empty() {
+                info!("{:?} is valid", path);
+            } else {
+                for problem in &problems {
+                    error!("{:?}: {}", path, problem);
+                }
+                std::process::exit(1);
             }
         }
         Some(Commands::Install) => {
@@ -168,3 +356,21 @@ fn main() {
         }
     }
 }
+
+/// Loads the config file from its default location; a missing file means the defaults.
+#[cfg(windows)]
+fn load_config() -> anyhow::Result<config::Config> {
+    match config::config_path() {
+        Some(path) => config::Config::load(&path),
+        None => Ok(config::Config::default()),
+    }
+}
+
+/// Loads the config for read-only commands, where a broken config is reported but not fatal.
+#[cfg(windows)]
+fn load_config_for_reading() -> config::Config {
+    load_config().unwrap_or_else(|e| {
+        warn!("{:#}. Using the default config.", e);
+        config::Config::default()
+    })
+}
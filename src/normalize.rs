@@ -0,0 +1,180 @@
+//! # PATH Entry Normalization
+//!
+//! Pure string logic for comparing PATH entries the way Windows resolves them.
+//! Nothing here touches the Registry or the file system, so it is tested on every platform.
+
+/// Expands `%VAR%` references using `lookup`, mirroring `ExpandEnvironmentStrings`.
+///
+/// - Variable names are passed to `lookup` as written; it decides case sensitivity.
+/// - Unknown variables and unpaired `%` are left untouched.
+///
+/// # Examples
+/// - `%USERPROFILE%\.cargo\bin` -> `C:\Users\me\.cargo\bin`
+/// - `%NOPE%\bin` -> `%NOPE%\bin`
+pub fn expand_env_vars(value: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('%') {
+            Some(end) => {
+                let name = &after[..end];
+                match (!name.is_empty()).then(|| lookup(name)).flatten() {
+                    Some(expanded) => {
+                        out.push_str(&expanded);
+                        rest = &after[end + 1..];
+                    }
+                    None => {
+                        // Keep the first '%' and retry from the closing one: it may open a real reference.
+                        out.push('%');
+                        out.push_str(name);
+                        rest = &after[end..];
+                    }
+                }
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Canonicalizes a PATH entry into the form Windows would resolve it to, for comparison.
+///
+/// Two entries are the same directory exactly when their canonical forms are equal:
+/// - `%VAR%` references are expanded via `lookup`.
+/// - Surrounding whitespace and all `"` quotes are removed (cmd.exe ignores them in PATH).
+/// - `/` becomes `\`, repeated separators collapse, `.` segments vanish and `..` pops a segment
+///   (but never above the root).
+/// - `\\?\C:\x` and `\\.\C:\x` become `C:\x`; `\\?\UNC\server\share` becomes `\\server\share`.
+/// - The leading `\\` of a UNC path is kept; trailing separators are dropped except for a drive root (`c:\`).
+/// - Everything is lowercased: drive letters, UNC server and share names, and NTFS paths are all case-insensitive.
+///
+/// The result is only a comparison key; the original spelling is what gets written back.
+pub fn canonicalize(entry: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let expanded = expand_env_vars(entry.trim(), lookup);
+    let unquoted: String = expanded.chars().filter(|&c| c != '"').collect();
+    let mut s = unquoted.trim().replace('/', "\\");
+
+    // Long-path and device prefixes
+    if s.get(..8).is_some_and(|prefix| prefix.eq_ignore_ascii_case(r"\\?\UNC\")) {
+        s = format!(r"\\{}", &s[8..]);
+    } else if s.starts_with(r"\\?\") || s.starts_with(r"\\.\") {
+        s = s[4..].to_string();
+    }
+
+    // Split off the root: `\\server\share`, `C:\`, `C:` (drive-relative), `\` (root-relative) or nothing.
+    let (root, rest): (String, &str) = if let Some(unc) = s.strip_prefix(r"\\") {
+        let mut parts = unc.splitn(3, '\\');
+        let server = parts.next().unwrap_or_default();
+        let share = parts.next().unwrap_or_default();
+        let root = if share.is_empty() { format!(r"\\{}", server) } else { format!(r"\\{}\{}", server, share) };
+        (root, parts.next().unwrap_or_default())
+    } else if s.len() >= 2 && s.as_bytes()[1] == b':' && s.as_bytes()[0].is_ascii_alphabetic() {
+        if s[2..].starts_with('\\') {
+            (format!(r"{}\", &s[..2]), &s[3..])
+        } else {
+            (s[..2].to_string(), &s[2..])
+        }
+    } else if let Some(rest) = s.strip_prefix('\\') {
+        (r"\".to_string(), rest)
+    } else {
+        (String::new(), s.as_str())
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in rest.split('\\') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.last().is_some_and(|last| *last != "..") {
+                    segments.pop();
+                } else if root.is_empty() {
+                    // Relative entries may legitimately climb; absolute ones stop at the root
+                    segments.push("..");
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    let joined = segments.join(r"\");
+    let canonical = if root.starts_with(r"\\") && !joined.is_empty() {
+        format!(r"{}\{}", root, joined)
+    } else {
+        format!("{}{}", root, joined)
+    };
+    canonical.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name.to_ascii_uppercase().as_str() {
+            "USERPROFILE" => Some(r"C:\Users\me".to_string()),
+            "SYSTEMROOT" => Some(r"C:\Windows".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        assert_eq!(expand_env_vars(r"%USERPROFILE%\.cargo\bin", env), r"C:\Users\me\.cargo\bin");
+        assert_eq!(expand_env_vars(r"%SystemRoot%\system32", env), r"C:\Windows\system32");
+        assert_eq!(expand_env_vars(r"C:\plain", env), r"C:\plain");
+    }
+
+    #[test]
+    fn test_expand_env_vars_leaves_unknown_and_unpaired() {
+        assert_eq!(expand_env_vars(r"%NOPE%\bin", env), r"%NOPE%\bin");
+        assert_eq!(expand_env_vars(r"C:\100%", env), r"C:\100%");
+        assert_eq!(expand_env_vars(r"%%", env), r"%%");
+        assert_eq!(expand_env_vars(r"50%%SystemRoot%", env), r"50%C:\Windows");
+    }
+
+    #[test]
+    fn test_canonicalize_equivalent_spellings() {
+        let env = |name: &str| env(name).or_else(|| name.eq_ignore_ascii_case("SystemDrive").then(|| "C:".to_string()));
+        for spelling in [
+            r"C:\Tools\",
+            r"C:\Tools",
+            r#""C:\Tools""#,
+            r"C:/Tools",
+            r"C:\Tools\.\",
+            r"c:\TOOLS\\",
+            r"C:\Tools\bin\..",
+            r"%SystemDrive%\Tools",
+            r"\\?\C:\Tools",
+            r"  C:\Tools  ",
+        ] {
+            assert_eq!(canonicalize(spelling, env), r"c:\tools", "spelling: {}", spelling);
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_roots_and_unc() {
+        assert_eq!(canonicalize(r"C:\", env), r"c:\");
+        assert_eq!(canonicalize(r"C:\..\..", env), r"c:\");
+        assert_eq!(canonicalize(r"C:", env), r"c:");
+        assert_eq!(canonicalize(r"\\Server\Share\Tools\", env), r"\\server\share\tools");
+        assert_eq!(canonicalize(r"//server/share/tools", env), r"\\server\share\tools");
+        assert_eq!(canonicalize(r"\\?\UNC\Server\Share\Tools", env), r"\\server\share\tools");
+        assert_eq!(canonicalize(r"\\server\share\..", env), r"\\server\share");
+        assert_eq!(canonicalize(r"\Tools\.", env), r"\tools");
+        assert_eq!(canonicalize(r"..\bin\.\x\..", env), r"..\bin");
+        assert_eq!(canonicalize(r"%NOPE%\bin", env), r"%nope%\bin");
+    }
+
+    #[test]
+    fn test_canonicalize_non_ascii_entries() {
+        assert_eq!(canonicalize(r"C:\ツール\bin\", env), r"c:\ツール\bin");
+        assert_eq!(canonicalize(r"\\?\C:\Ünïcode", env), r"c:\ünïcode");
+        assert_eq!(canonicalize("ツール", env), "ツール");
+    }
+}
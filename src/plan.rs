@@ -0,0 +1,233 @@
+//! # Heal Plans
+//!
+//! A `HealPlan` is the complete, side-effect free description of what a heal would do:
+//! the User and System PATH before and after, plus a reason for every entry that is
+//! added or removed.
+//!
+//! Plans are produced by `cleaner::plan_healing`, rendered by the CLI, serialized to JSON
+//! for review, and finally executed by `cleaner::apply_plan`.
+
+use std::fmt;
+use std::path::Path;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::budget::LengthBudget;
+
+/// Which of the two Windows PATH variables an entry belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// `HKCU\Environment\Path`
+    User,
+    /// `HKLM\...\Session Manager\Environment\Path`
+    System,
+}
+
+/// What happens to an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Add,
+    Remove,
+}
+
+/// Why an entry is being added or removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Reason {
+    /// Already in the System PATH, which Windows searches first.
+    DuplicateOfSystem,
+    /// Appears earlier in the same PATH.
+    Duplicate,
+    /// The directory does not exist.
+    MissingDirectory,
+    /// Windows directories belong in the System PATH, not the User PATH.
+    /// No longer produced (existing entries are kept); still accepted in older plan files.
+    WindowsDirectory,
+    /// Discovery found no executables in this directory.
+    /// No longer produced (existing entries are kept); still accepted in older plan files.
+    NoExecutables,
+    /// Found by one or more discovery sources (e.g. "scoop", "cargo").
+    Discovered { sources: Vec<String> },
+    /// Matches a `block` rule in the config.
+    Blocked,
+    /// Listed under `pin` in the config.
+    Pinned,
+    /// Rewritten to a shorter `%VAR%`-relative form to fit the length limits (`heal --compact`).
+    Shortened { to: String },
+    /// Dropped from the end of the User PATH to fit the length limits (`heal --compact`).
+    OverLengthBudget,
+    /// Moved to the scope it belongs in (`heal --rebalance`). Recorded on both sides of the move.
+    Moved { from: Scope, to: Scope },
+    /// The chosen JDK's `bin`, placed in front of Oracle's `javapath` shims (see `java`).
+    AheadOfJavapath,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::DuplicateOfSystem => write!(f, "duplicate of system"),
+            Reason::Duplicate => write!(f, "duplicate"),
+            Reason::MissingDirectory => write!(f, "missing directory"),
+            Reason::WindowsDirectory => write!(f, "Windows directory"),
+            Reason::NoExecutables => write!(f, "no executables found"),
+            Reason::Discovered { sources } => write!(f, "discovered via {}", sources.join(", ")),
+            Reason::Blocked => write!(f, "blocked by config"),
+            Reason::Pinned => write!(f, "pinned by config"),
+            Reason::Shortened { to } => write!(f, "shortened to {}", to),
+            Reason::OverLengthBudget => write!(f, "dropped to fit the PATH length limit"),
+            Reason::Moved { from, to } => write!(f, "moved from {:?} to {:?} PATH", from, to),
+            Reason::AheadOfJavapath => write!(f, "chosen JDK, ahead of Oracle's javapath"),
+        }
+    }
+}
+
+/// What decides an entry's position in the new User PATH, and so which copy of a
+/// command wins when several folders have it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Rank {
+    /// Matches a `pin` rule; `pin` is the rule's position in the config, 1 first.
+    Pinned { pin: usize },
+    /// Found by discovery; `rank` is the best source's position in `source_priority`, 1 first.
+    Source { source: String, rank: usize },
+    /// Neither pinned nor discovered, e.g. a folder of scripts kept from the current PATH.
+    Unranked,
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rank::Pinned { pin } => write!(f, "pin #{}", pin),
+            Rank::Source { source, rank } => write!(f, "{}, rank {}", source, rank),
+            Rank::Unranked => write!(f, "unranked, kept from the current PATH"),
+        }
+    }
+}
+
+/// An entry of the new User PATH with its rank.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RankedEntry {
+    /// The entry exactly as it will be written.
+    pub path: String,
+    pub rank: Rank,
+}
+
+/// A single entry that the plan adds or removes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanChange {
+    pub scope: Scope,
+    pub action: Action,
+    /// The entry exactly as it appears in the PATH value.
+    pub path: String,
+    pub reason: Reason,
+}
+
+/// The full result of planning a heal. Producing one never touches the system.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealPlan {
+    pub user_path_before: String,
+    pub user_path_after: String,
+    pub system_path_before: String,
+    pub system_path_after: String,
+    /// Every added or removed entry, User scope first, in PATH order.
+    pub changes: Vec<PlanChange>,
+    /// The new User PATH in order, with what placed each entry there.
+    #[serde(default)]
+    pub user_order: Vec<RankedEntry>,
+    /// Lengths of the combined PATH before the plan.
+    #[serde(default)]
+    pub length_before: LengthBudget,
+    /// Lengths of the combined PATH after the plan.
+    #[serde(default)]
+    pub length_after: LengthBudget,
+    /// `JAVA_HOME` in the User environment before the plan, if set.
+    #[serde(default)]
+    pub java_home_before: Option<String>,
+    /// `JAVA_HOME` after the plan. Only differs from `java_home_before` with `java.set_java_home`.
+    #[serde(default)]
+    pub java_home_after: Option<String>,
+}
+
+impl HealPlan {
+    /// True if applying this plan would not change either PATH, nor `JAVA_HOME`.
+    pub fn is_noop(&self) -> bool {
+        !self.user_changed() && !self.system_changed() && !self.java_home_changed()
+    }
+
+    /// True if the User PATH value differs after the plan.
+    pub fn user_changed(&self) -> bool {
+        self.user_path_before != self.user_path_after
+    }
+
+    /// True if the System PATH value differs after the plan.
+    pub fn system_changed(&self) -> bool {
+        self.system_path_before != self.system_path_after
+    }
+
+    /// True if the User `JAVA_HOME` differs after the plan.
+    pub fn java_home_changed(&self) -> bool {
+        self.java_home_before != self.java_home_after
+    }
+
+    /// The changes for one scope and action, in PATH order.
+    pub fn changes_for(&self, scope: Scope, action: Action) -> impl Iterator<Item = &PlanChange> {
+        self.changes.iter().filter(move |c| c.scope == scope && c.action == action)
+    }
+
+    /// Serialize the plan as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse a plan previously written by `to_json`.
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Write the plan to a JSON file for review (`heal --plan-out`).
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_json()?)
+            .with_context(|| format!("Failed to write plan to {:?}", path))
+    }
+
+    /// Read a reviewed plan back from disk (`apply`).
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plan from {:?}", path))?;
+        Self::from_json(&json).with_context(|| format!("{:?} is not a valid Wanderlust plan", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_json_round_trip() {
+        let plan = HealPlan {
+            user_path_before: r"C:\Tools;C:\Tools".to_string(),
+            user_path_after: r"C:\Tools".to_string(),
+            system_path_before: String::new(),
+            system_path_after: String::new(),
+            changes: vec![PlanChange {
+                scope: Scope::User,
+                action: Action::Add,
+                path: r"C:\Users\me\scoop\shims".to_string(),
+                reason: Reason::Discovered { sources: vec!["scoop".to_string()] },
+            }],
+            user_order: vec![RankedEntry {
+                path: r"C:\Users\me\scoop\shims".to_string(),
+                rank: Rank::Source { source: "scoop".to_string(), rank: 2 },
+            }],
+            length_before: LengthBudget::default(),
+            length_after: LengthBudget::default(),
+            java_home_before: None,
+            java_home_after: Some(r"C:\Program Files\Eclipse Adoptium\jdk-21".to_string()),
+        };
+
+        let json = plan.to_json().unwrap();
+        assert!(json.contains(r#""kind": "discovered""#), "Reasons are tagged for tooling: {}", json);
+        assert_eq!(HealPlan::from_json(&json).unwrap(), plan);
+    }
+}
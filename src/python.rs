@@ -0,0 +1,386 @@
+//! # Python Discovery
+//!
+//! Finds Python the way Python itself expects to be found on Windows:
+//!
+//! 1.  **PEP 514**: interpreters registered under `Software\Python\<Company>\<Tag>` in HKCU
+//!     and HKLM (including the 32-bit `WOW6432Node` view). Unregistered `Python3*`
+//!     directories in the usual install locations are picked up as well.
+//! 2.  **The `py` launcher**: the per-user install in `%LOCALAPPDATA%\Programs\Python\Launcher`.
+//! 3.  **pyenv-win**: its `bin` directory, and its `shims` when pyenv picks the interpreter.
+//! 4.  **conda**: the `condabin` directory of the base install.
+//! 5.  **pipx and uv**: their tool directories, when moved away from `~\.local\bin`
+//!     (which the `local_bin` source covers).
+//!
+//! Only one interpreter is added to the PATH, chosen by `config::PythonDefault`. Every
+//! other interpreter stays reachable through `py -3.11` and friends.
+//!
+//! The Microsoft Store puts a `python.exe` stub into `%LOCALAPPDATA%\Microsoft\WindowsApps`
+//! that only opens the Store. `is_store_stub` recognises it, so `doctor` can report it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use log::{debug, warn};
+use crate::config::PythonDefault;
+use crate::discovery::{self, Candidate};
+use crate::normalize;
+use crate::system::{DiscoveryOps, Hive};
+
+/// The PEP 514 registry roots, with the hive each one is read from.
+const PEP514_KEYS: &[(Hive, &str)] = &[
+    (Hive::CurrentUser, r"Software\Python"),
+    (Hive::LocalMachine, r"Software\Python"),
+    (Hive::LocalMachine, r"Software\WOW6432Node\Python"),
+];
+
+/// The company name PEP 514 reserves for the `py` launcher; it is not an interpreter.
+const LAUNCHER_COMPANY: &str = "PyLauncher";
+
+/// Directory names conda installers use for the base install.
+const CONDA_DIRS: &[&str] = &["miniconda3", "anaconda3", "miniforge3", "mambaforge"];
+
+/// An installed Python interpreter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpreter {
+    /// The version as registered (e.g. `3.12.1`) or derived from the directory name (`3.12`).
+    pub version: String,
+    /// The directory containing `python.exe`.
+    pub dir: PathBuf,
+    /// Installed for all users (HKLM, or outside the user's profile).
+    pub machine_wide: bool,
+    /// A 32-bit build (a `-32` tag or directory suffix).
+    pub is_32bit: bool,
+}
+
+impl Interpreter {
+    /// The numeric version components, for comparing versions (`3.9` < `3.12`).
+    fn version_key(&self) -> Vec<u32> {
+        version_key(&self.version)
+    }
+}
+
+/// `3.12.1` -> `[3, 12, 1]`; stops at the first non-numeric component (`3.13t` -> `[3, 13]`).
+fn version_key(version: &str) -> Vec<u32> {
+    let mut key = Vec::new();
+    for part in version.split('.') {
+        let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+        match digits.parse() {
+            Ok(n) => key.push(n),
+            Err(_) => break,
+        }
+        if digits.len() != part.len() {
+            break;
+        }
+    }
+    key
+}
+
+/// The interpreters registered according to PEP 514.
+pub fn registered_interpreters(system: &impl DiscoveryOps) -> Vec<Interpreter> {
+    let mut found = Vec::new();
+    for &(hive, root) in PEP514_KEYS {
+        for company in system.registry_subkeys(hive, root) {
+            if company.eq_ignore_ascii_case(LAUNCHER_COMPANY) {
+                continue;
+            }
+            let company_key = format!(r"{}\{}", root, company);
+            for tag in system.registry_subkeys(hive, &company_key) {
+                let tag_key = format!(r"{}\{}", company_key, tag);
+                let install_path = format!(r"{}\InstallPath", tag_key);
+                let dir = system.registry_string(hive, &install_path, "")
+                    .filter(|s| !s.is_empty())
+                    .map(|s| PathBuf::from(s.trim_end_matches('\\')))
+                    .or_else(|| {
+                        let exe = system.registry_string(hive, &install_path, "ExecutablePath")?;
+                        Path::new(&exe).parent().map(Path::to_path_buf)
+                    });
+                let Some(dir) = dir.filter(|dir| system.is_dir(dir)) else {
+                    debug!("Skipping Python {}\\{}: no install directory", company, tag);
+                    continue;
+                };
+                let version = system.registry_string(hive, &tag_key, "Version")
+                    .or_else(|| system.registry_string(hive, &tag_key, "SysVersion"))
+                    .unwrap_or_else(|| tag.clone());
+                found.push(Interpreter {
+                    version,
+                    dir,
+                    machine_wide: hive == Hive::LocalMachine,
+                    is_32bit: tag.ends_with("-32") || root.contains("WOW6432Node"),
+                });
+            }
+        }
+    }
+    found
+}
+
+/// `Python3*` directories (with a `python.exe`) in the usual install locations that PEP 514
+/// does not know about, e.g. after a manual copy.
+fn unregistered_interpreters(system: &impl DiscoveryOps) -> Vec<Interpreter> {
+    let mut bases: Vec<(PathBuf, bool)> = Vec::new();
+    if let Some(local) = discovery::local_app_data(system) {
+        bases.push((local.join("Programs").join("Python"), false));
+    }
+    for drive in ["C:", "D:"] {
+        let drive_path = PathBuf::from(drive);
+        bases.push((drive_path.clone(), true));
+        for pf in ["Program Files", "Program Files (x86)"] {
+            bases.push((drive_path.join(pf), true));
+        }
+    }
+
+    let mut found = Vec::new();
+    for (base, machine_wide) in bases {
+        for dir in system.list_dir(&base) {
+            let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let Some(digits) = name.strip_prefix("Python3") else {
+                continue;
+            };
+            if !system.is_dir(&dir) || !system.is_file(&dir.join("python.exe")) {
+                continue;
+            }
+            // Python312 -> 3.12, Python312-32 -> 3.12
+            let minor: String = digits.chars().take_while(|c| c.is_ascii_digit()).collect();
+            let version = if minor.is_empty() { "3".to_string() } else { format!("3.{}", minor) };
+            found.push(Interpreter { version, is_32bit: digits.ends_with("-32"), dir, machine_wide });
+        }
+    }
+    found
+}
+
+/// Every installed interpreter, registered ones first, each directory once.
+pub fn installed_interpreters(system: &impl DiscoveryOps) -> Vec<Interpreter> {
+    let key = |dir: &Path| normalize::canonicalize(&dir.to_string_lossy(), |_| None);
+    let mut seen = std::collections::HashSet::new();
+    registered_interpreters(system).into_iter()
+        .chain(unregistered_interpreters(system))
+        .filter(|i| seen.insert(key(&i.dir)))
+        .collect()
+}
+
+/// The newest interpreter, 64-bit builds first. With `prefix` (e.g. `[3, 11]`), only versions
+/// starting with it are considered.
+fn newest<'a>(interpreters: &'a [Interpreter], prefix: &[u32]) -> Option<&'a Interpreter> {
+    interpreters.iter()
+        .filter(|i| i.version_key().starts_with(prefix))
+        .max_by_key(|i| (i.version_key(), !i.is_32bit))
+}
+
+/// pyenv-win's root: `%PYENV%` (or `%PYENV_ROOT%`, `%PYENV_HOME%`), or `~\.pyenv\pyenv-win`.
+fn pyenv_root(system: &impl DiscoveryOps) -> Option<PathBuf> {
+    ["PYENV", "PYENV_ROOT", "PYENV_HOME"].iter()
+        .find_map(|name| discovery::environment_variable(system, name))
+        .map(PathBuf::from)
+        .or_else(|| system.home_dir().map(|home| home.join(".pyenv").join("pyenv-win")))
+        .filter(|root| system.is_dir(&root.join("bin")))
+}
+
+/// The conda base install: the one running this shell (`%CONDA_EXE%` is `<base>\Scripts\conda.exe`),
+/// or the first install found in the profile, `%LOCALAPPDATA%` or `%ProgramData%`.
+fn conda_base(system: &impl DiscoveryOps) -> Option<PathBuf> {
+    let active = system.env_var("CONDA_EXE")
+        .and_then(|exe| Some(Path::new(&exe).parent()?.parent()?.to_path_buf()));
+    let parents = [system.home_dir(), discovery::local_app_data(system), system.env_var("ProgramData").map(PathBuf::from)];
+    let installed = parents.into_iter().flatten()
+        .flat_map(|parent| CONDA_DIRS.iter().map(move |name| parent.join(name)));
+    active.into_iter()
+        .chain(installed)
+        .find(|base| system.is_dir(&base.join("condabin")) && system.is_dir(&base.join("conda-meta")))
+}
+
+/// Directories holding versioned installs owned by pyenv or conda. On the PATH they would fight
+/// `pyenv global` / `conda activate`, so discovery never adds anything inside them.
+pub fn versioned_roots(system: &impl DiscoveryOps) -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if let Some(root) = pyenv_root(system) {
+        roots.push(root.join("versions"));
+    }
+    if let Some(base) = conda_base(system) {
+        roots.push(base.join("envs"));
+        roots.push(base.join("pkgs"));
+    }
+    roots
+}
+
+/// Adds the Python directories to `map` (see the module docs), with the single interpreter
+/// chosen by `policy`.
+pub(crate) fn scan(system: &impl DiscoveryOps, policy: &PythonDefault, map: &mut HashMap<String, Vec<Candidate>>) {
+    let mut add = |dir: &Path, source: &str| {
+        if system.is_dir(dir) {
+            discovery::add_dir_candidates(system, map, dir, source);
+        }
+    };
+
+    if let Some(local) = discovery::local_app_data(system) {
+        add(&local.join("Programs").join("Python").join("Launcher"), "py_launcher");
+    }
+
+    let pyenv = pyenv_root(system);
+    if let Some(root) = &pyenv {
+        add(&root.join("bin"), "pyenv");
+    }
+    let conda = conda_base(system);
+    if let Some(base) = &conda {
+        add(&base.join("condabin"), "conda");
+    }
+    for name in ["PIPX_BIN_DIR", "UV_TOOL_BIN_DIR", "XDG_BIN_HOME"] {
+        if let Some(dir) = system.env_var(name).filter(|s| !s.is_empty()) {
+            add(Path::new(&dir), "python_tools");
+        }
+    }
+
+    let interpreters = installed_interpreters(system);
+    let chosen = match policy {
+        PythonDefault::Auto if pyenv.is_some() => None,
+        PythonDefault::Auto | PythonDefault::Newest => newest(&interpreters, &[]),
+        PythonDefault::Version(version) => {
+            let chosen = newest(&interpreters, &version_key(version));
+            if chosen.is_none() {
+                warn!("No Python {} is installed; not adding a Python interpreter", version);
+            }
+            chosen
+        }
+        PythonDefault::Pyenv | PythonDefault::Conda | PythonDefault::None => None,
+    };
+
+    match policy {
+        PythonDefault::Auto | PythonDefault::Pyenv if chosen.is_none() => {
+            if let Some(root) = &pyenv {
+                add(&root.join("shims"), "pyenv");
+            }
+        }
+        PythonDefault::Conda => {
+            if let Some(base) = &conda {
+                add(base, "conda");
+                add(&base.join("Library").join("bin"), "conda");
+                add(&base.join("Scripts"), "conda");
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(interpreter) = chosen {
+        debug!("Default Python: {} in {:?}", interpreter.version, interpreter.dir);
+        add(&interpreter.dir, if interpreter.machine_wide { "python_system" } else { "python" });
+        add(&interpreter.dir.join("Scripts"), "python_scripts");
+    }
+}
+
+/// Is `file` the Microsoft Store's `python.exe` / `python3.exe` stub, which opens the Store
+/// instead of running Python? A real Store install of Python puts its own aliases into the
+/// same `WindowsApps` directory, next to a `PythonSoftwareFoundation.Python.3.*` package
+/// directory; without one, the alias is the stub.
+pub fn is_store_stub(system: &impl DiscoveryOps, file: &Path) -> bool {
+    let Some(dir) = file.parent() else {
+        return false;
+    };
+    let name = file.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    let in_windows_apps = normalize::canonicalize(&dir.to_string_lossy(), |_| None).ends_with(r"\microsoft\windowsapps");
+    in_windows_apps
+        && (name == "python.exe" || name == "python3.exe")
+        && !system.list_dir(dir).iter().any(|p| {
+            p.file_name().unwrap_or_default().to_string_lossy().starts_with("PythonSoftwareFoundation.Python.")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    const PYTHON_CORE: &str = r"Software\Python\PythonCore";
+
+    fn register(system: &MockSystem, hive: Hive, tag: &str, version: &str, dir: &str) {
+        system.set_registry_string(hive, &format!(r"{}\{}", PYTHON_CORE, tag), "Version", version);
+        system.set_registry_string(hive, &format!(r"{}\{}\InstallPath", PYTHON_CORE, tag), "", dir);
+        system.add_file(format!("{}/python.exe", dir));
+    }
+
+    fn dirs(map: &HashMap<String, Vec<Candidate>>, cmd: &str) -> Vec<(PathBuf, String)> {
+        map.get(cmd).into_iter().flatten().map(|c| (c.path.clone(), c.source.clone())).collect()
+    }
+
+    #[test]
+    fn test_version_key() {
+        assert_eq!(version_key("3.12.1"), vec![3, 12, 1]);
+        assert_eq!(version_key("3.13t"), vec![3, 13]);
+        assert_eq!(version_key("3.11-32"), vec![3, 11]);
+        assert!(version_key("3.9") < version_key("3.12"));
+    }
+
+    #[test]
+    fn test_pep514_selects_one_interpreter() {
+        let system = MockSystem::new();
+        system.set_home("/home/me");
+        register(&system, Hive::LocalMachine, "3.9", "3.9.13", "/opt/Python39");
+        register(&system, Hive::CurrentUser, "3.12", "3.12.1", "/home/me/Python312");
+        register(&system, Hive::CurrentUser, "3.12-32", "3.12.1", "/home/me/Python312-32");
+        register(&system, Hive::LocalMachine, "3.11", "3.11.7", "/opt/Python311");
+        system.add_file("/opt/Python311/Scripts/pip.exe");
+        system.set_registry_string(Hive::CurrentUser, r"Software\Python\PyLauncher\3", "Version", "3");
+
+        let mut map = HashMap::new();
+        scan(&system, &PythonDefault::Newest, &mut map);
+        assert_eq!(dirs(&map, "python"), vec![(PathBuf::from("/home/me/Python312"), "python".to_string())],
+            "Newest version, 64-bit first");
+
+        let mut map = HashMap::new();
+        scan(&system, &PythonDefault::Version("3.11".to_string()), &mut map);
+        assert_eq!(dirs(&map, "python"), vec![(PathBuf::from("/opt/Python311"), "python_system".to_string())]);
+        assert_eq!(dirs(&map, "pip"), vec![(PathBuf::from("/opt/Python311/Scripts"), "python_scripts".to_string())]);
+
+        let mut map = HashMap::new();
+        scan(&system, &PythonDefault::Version("3.10".to_string()), &mut map);
+        assert!(map.is_empty(), "No 3.10 installed: {:?}", map);
+        assert_eq!(registered_interpreters(&system).len(), 4);
+    }
+
+    #[test]
+    fn test_managers_and_launcher() {
+        let system = MockSystem::new();
+        system.set_home("/home/me");
+        system.set_env("LOCALAPPDATA", "/home/me/AppData/Local");
+        system.add_file("/home/me/AppData/Local/Programs/Python/Launcher/py.exe");
+        system.add_file("/home/me/AppData/Local/Programs/Python/Python313/python.exe");
+        system.add_file("/home/me/.pyenv/pyenv-win/bin/pyenv.bat");
+        system.add_file("/home/me/.pyenv/pyenv-win/shims/python.bat");
+        system.add_file("/home/me/.pyenv/pyenv-win/versions/3.12.1/python.exe");
+        system.add_file("/home/me/miniconda3/condabin/conda.bat");
+        system.add_file("/home/me/miniconda3/python.exe");
+        system.add_dir("/home/me/miniconda3/conda-meta");
+        system.set_env("PIPX_BIN_DIR", "/home/me/pipx/bin");
+        system.add_file("/home/me/pipx/bin/black.exe");
+
+        let mut map = HashMap::new();
+        scan(&system, &PythonDefault::Auto, &mut map);
+
+        assert_eq!(dirs(&map, "py"), vec![(PathBuf::from("/home/me/AppData/Local/Programs/Python/Launcher"), "py_launcher".to_string())]);
+        assert_eq!(dirs(&map, "pyenv"), vec![(PathBuf::from("/home/me/.pyenv/pyenv-win/bin"), "pyenv".to_string())]);
+        assert_eq!(dirs(&map, "python"), vec![(PathBuf::from("/home/me/.pyenv/pyenv-win/shims"), "pyenv".to_string())],
+            "pyenv picks the interpreter when it is installed");
+        assert_eq!(dirs(&map, "conda"), vec![(PathBuf::from("/home/me/miniconda3/condabin"), "conda".to_string())]);
+        assert_eq!(dirs(&map, "black"), vec![(PathBuf::from("/home/me/pipx/bin"), "python_tools".to_string())]);
+        assert_eq!(versioned_roots(&system), vec![
+            PathBuf::from("/home/me/.pyenv/pyenv-win/versions"),
+            PathBuf::from("/home/me/miniconda3/envs"),
+            PathBuf::from("/home/me/miniconda3/pkgs"),
+        ]);
+
+        let mut map = HashMap::new();
+        scan(&system, &PythonDefault::Conda, &mut map);
+        assert_eq!(dirs(&map, "python"), vec![(PathBuf::from("/home/me/miniconda3"), "conda".to_string())]);
+    }
+
+    #[test]
+    fn test_store_stub() {
+        let system = MockSystem::new();
+        let apps = "/home/me/AppData/Local/Microsoft/WindowsApps";
+        system.add_file(format!("{}/python.exe", apps));
+        system.add_file(format!("{}/winget.exe", apps));
+
+        assert!(is_store_stub(&system, &PathBuf::from(format!("{}/python.exe", apps))));
+        assert!(!is_store_stub(&system, &PathBuf::from(format!("{}/winget.exe", apps))));
+        assert!(!is_store_stub(&system, Path::new("/opt/Python312/python.exe")));
+
+        system.add_file(format!("{}/PythonSoftwareFoundation.Python.3.12_qbz5n2kfra8p0/python.exe", apps));
+        assert!(!is_store_stub(&system, &PathBuf::from(format!("{}/python.exe", apps))), "A real Store install");
+    }
+}
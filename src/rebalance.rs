@@ -0,0 +1,170 @@
+//! # Scope Rebalancing
+//!
+//! Installers often put per-user directories (`C:\Users\alice\...`) into the System PATH,
+//! where they are useless to every other user, and machine-wide ones (`C:\Program Files\...`)
+//! into a User PATH. With `rebalance` switched on, a heal moves such entries to the scope
+//! they belong in (see `owning_scope`).
+//!
+//! Moving changes precedence: Windows searches the System PATH first. A directory moved to
+//! the User PATH is placed right after the pinned entries, so it still wins over the rest
+//! of the User PATH; one moved to the System PATH is appended to it.
+//!
+//! Only the current user's profile can be recognised. Directories in other users' profiles
+//! are left where they are; they move when that user runs a heal.
+
+use std::collections::HashSet;
+use log::info;
+use crate::normalize;
+use crate::plan::Scope;
+
+/// Variables pointing at machine-wide locations.
+const MACHINE_VARIABLES: &[&str] = &["ProgramFiles", "ProgramFiles(x86)", "ProgramW6432", "ProgramData", "SystemRoot"];
+
+/// One entry moving between scopes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// The entry exactly as it appears in the PATH value it leaves.
+    pub entry: String,
+    /// The scope it leaves.
+    pub from: Scope,
+}
+
+/// The planned PATH values after rebalancing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rebalanced {
+    pub system_path: String,
+    pub user_path: String,
+    pub migrations: Vec<Migration>,
+}
+
+/// Which scope a directory belongs in, judged by its canonical key: `User` if it is inside the
+/// current user's profile, `System` if it is inside a machine-wide location, `None` if unclear.
+pub fn owning_scope(key: &str, lookup: impl Fn(&str) -> Option<String>) -> Option<Scope> {
+    let under = |name: &str| {
+        lookup(name)
+            .map(|value| normalize::canonicalize(&value, &lookup))
+            .filter(|root| !root.is_empty())
+            .is_some_and(|root| {
+                let prefix = if root.ends_with('\\') { root.clone() } else { format!("{}\\", root) };
+                key == root || key.starts_with(&prefix)
+            })
+    };
+    if under("USERPROFILE") {
+        Some(Scope::User)
+    } else if MACHINE_VARIABLES.iter().any(|name| under(name)) {
+        Some(Scope::System)
+    } else {
+        None
+    }
+}
+
+/// Moves entries between the planned System and User PATH values.
+///
+/// * System entries owned by the user move to the User PATH, right after its pinned entries.
+/// * Machine-wide User entries that were already in `user_path_before` move to the end of the
+///   System PATH. Newly discovered directories are left in the User PATH, and pinned ones stay
+///   where the pin puts them.
+///
+/// `key` gives an entry's canonical key, `is_pinned` tells whether a key is pinned.
+pub fn rebalance(
+    system_path: &str,
+    user_path: &str,
+    user_path_before: &str,
+    key: impl Fn(&str) -> String,
+    is_pinned: impl Fn(&str) -> bool,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Rebalanced {
+    let entries = |value: &str| -> Vec<String> {
+        value.split(';').filter(|s| !s.is_empty()).map(str::to_string).collect()
+    };
+    let existing: HashSet<String> = entries(user_path_before).iter().map(|e| key(e)).collect();
+    let mut migrations = Vec::new();
+
+    let (to_user, mut system_entries): (Vec<String>, Vec<String>) = entries(system_path)
+        .into_iter()
+        .partition(|e| owning_scope(&key(e), &lookup) == Some(Scope::User));
+    let (to_system, mut user_entries): (Vec<String>, Vec<String>) = entries(user_path)
+        .into_iter()
+        .partition(|e| {
+            let k = key(e);
+            existing.contains(&k) && !is_pinned(&k) && owning_scope(&k, &lookup) == Some(Scope::System)
+        });
+
+    let pinned = user_entries.iter().take_while(|e| is_pinned(&key(e))).count();
+    let user_keys: HashSet<String> = user_entries.iter().map(|e| key(e)).collect();
+    let mut insert_at = pinned;
+    for entry in to_user {
+        info!("Moving {} from the System PATH to the User PATH", entry);
+        // Also in the User PATH already: the System spelling moves, the User one stays
+        if !user_keys.contains(&key(&entry)) {
+            user_entries.insert(insert_at, entry.clone());
+            insert_at += 1;
+        }
+        migrations.push(Migration { entry, from: Scope::System });
+    }
+    let system_keys: HashSet<String> = system_entries.iter().map(|e| key(e)).collect();
+    for entry in to_system {
+        info!("Moving {} from the User PATH to the System PATH", entry);
+        if !system_keys.contains(&key(&entry)) {
+            system_entries.push(entry.clone());
+        }
+        migrations.push(Migration { entry, from: Scope::User });
+    }
+
+    if migrations.is_empty() {
+        return Rebalanced { system_path: system_path.to_string(), user_path: user_path.to_string(), migrations };
+    }
+    Rebalanced {
+        system_path: system_entries.join(";"),
+        user_path: user_entries.join(";"),
+        migrations,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "USERPROFILE" => Some(r"C:\Users\alice".to_string()),
+            "ProgramFiles" => Some(r"C:\Program Files".to_string()),
+            "SystemRoot" => Some(r"C:\Windows".to_string()),
+            _ => None,
+        }
+    }
+
+    fn key(entry: &str) -> String {
+        normalize::canonicalize(entry, lookup)
+    }
+
+    #[test]
+    fn test_owning_scope() {
+        assert_eq!(owning_scope(&key(r"C:\Users\alice\.cargo\bin"), lookup), Some(Scope::User));
+        assert_eq!(owning_scope(&key(r"%USERPROFILE%\scoop\shims"), lookup), Some(Scope::User));
+        assert_eq!(owning_scope(&key(r"C:\Program Files\Git\cmd"), lookup), Some(Scope::System));
+        assert_eq!(owning_scope(&key(r"C:\Windows\System32"), lookup), Some(Scope::System));
+        assert_eq!(owning_scope(&key(r"C:\Users\alice2\bin"), lookup), None, "Another user's profile");
+        assert_eq!(owning_scope(&key(r"D:\tools"), lookup), None);
+    }
+
+    #[test]
+    fn test_rebalance_moves_entries_both_ways() {
+        let system = r"C:\Windows;C:\Users\alice\AppData\Local\Programs\Tool;C:\Users\bob\bin";
+        let user_before = r"D:\corp\bin;C:\Program Files\Git\cmd;C:\Users\alice\.cargo\bin";
+        let user = r"D:\corp\bin;C:\Program Files\Git\cmd;C:\Users\alice\.cargo\bin;C:\Program Files\nodejs";
+
+        let result = rebalance(system, user, user_before, key, |k| k == r"d:\corp\bin", lookup);
+
+        assert_eq!(result.system_path, r"C:\Windows;C:\Users\bob\bin;C:\Program Files\Git\cmd");
+        assert_eq!(result.user_path, r"D:\corp\bin;C:\Users\alice\AppData\Local\Programs\Tool;C:\Users\alice\.cargo\bin;C:\Program Files\nodejs");
+        assert_eq!(result.migrations, vec![
+            Migration { entry: r"C:\Users\alice\AppData\Local\Programs\Tool".to_string(), from: Scope::System },
+            Migration { entry: r"C:\Program Files\Git\cmd".to_string(), from: Scope::User },
+        ]);
+
+        let unchanged = rebalance(r"C:\Windows", r"D:\tools", r"D:\tools", key, |_| false, lookup);
+        assert!(unchanged.migrations.is_empty());
+        assert_eq!(unchanged.user_path, r"D:\tools");
+    }
+}
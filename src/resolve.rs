@@ -0,0 +1,397 @@
+//! # Command Resolution
+//!
+//! Mirrors how Windows finds a command typed without a directory:
+//! 1. The current directory (cmd.exe only, unless `NoDefaultCurrentDirectoryInExePath` is set).
+//! 2. Every PATH directory in order: System PATH first, then User PATH.
+//! 3. `App Paths` registry entries, which only the shell (Start menu, Run dialog) consults.
+//!
+//! Within a directory the extensions in `PATHEXT` are tried in order; a name that already
+//! has an extension is tried as-is first. The first match wins.
+//!
+//! File system access goes through `system::DiscoveryOps`, so resolution can be tested
+//! against a `MockSystem` directory tree on any platform.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::discovery::{self, Candidate};
+use crate::normalize;
+use crate::plan::Scope;
+use crate::system::{DiscoveryOps, Hive, SystemOps};
+
+/// Registry key (under HKCU and HKLM) whose `<name>.exe` subkeys register shell-only launch paths.
+const APP_PATHS: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths";
+
+/// The `PATHEXT` Windows uses when the variable is not set.
+pub const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD;.VBS;.VBE;.JS;.JSE;.WSF;.WSH;.MSC";
+
+/// The executable extensions from `PATHEXT`, lowercased and in precedence order (e.g. `.exe`).
+pub fn pathext(system: &impl DiscoveryOps) -> Vec<String> {
+    let value = system.env_var("PATHEXT").filter(|v| !v.trim().is_empty());
+    let mut seen = HashSet::new();
+    value.as_deref().unwrap_or(DEFAULT_PATHEXT)
+        .split(';')
+        .map(|ext| ext.trim().to_lowercase())
+        .filter(|ext| ext.starts_with('.') && ext.len() > 1)
+        .filter(|ext| seen.insert(ext.clone()))
+        .collect()
+}
+
+/// One directory in the effective search order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchDir {
+    pub scope: Scope,
+    /// The entry exactly as it appears in the PATH value.
+    pub entry: String,
+    /// The entry with `%VAR%` references expanded.
+    pub dir: PathBuf,
+}
+
+/// The directories Windows searches, in order: System PATH, then User PATH.
+///
+/// Repeated directories (in canonical form) are only searched once, like they are in practice.
+pub fn search_dirs(system: &impl SystemOps) -> Vec<SearchDir> {
+    let scopes = [
+        (Scope::System, system.read_system_path_registry().unwrap_or_default()),
+        (Scope::User, system.read_user_path_registry().unwrap_or_default()),
+    ];
+    let mut seen = HashSet::new();
+    let mut dirs = Vec::new();
+    for (scope, value) in scopes {
+        for entry in value.split(';').filter(|s| !s.trim().is_empty()) {
+            if seen.insert(normalize::canonicalize(entry, |name| system.lookup_env_var(name))) {
+                let dir = PathBuf::from(system.expand_env_vars(entry.trim()).replace('"', ""));
+                dirs.push(SearchDir { scope, entry: entry.to_string(), dir });
+            }
+        }
+    }
+    dirs
+}
+
+/// A file that a command name resolves to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    pub scope: Scope,
+    /// The PATH entry the file was found through.
+    pub entry: String,
+    pub file: PathBuf,
+}
+
+/// A command found in more than one place on the PATH.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadow {
+    pub command: String,
+    /// The file Windows runs.
+    pub winner: Hit,
+    /// Every other match, in the order Windows would have tried them.
+    pub shadowed: Vec<Hit>,
+}
+
+/// The file names tried for `command` within one directory, in order (lowercase).
+///
+/// `python` -> `python.com`, `python.exe`, ...; `python.exe` -> `python.exe`, `python.exe.com`, ...
+fn file_names(command: &str, pathext: &[String]) -> Vec<String> {
+    let command = command.to_lowercase();
+    let as_is = Path::new(&command).extension().is_some().then(|| command.clone());
+    as_is.into_iter()
+        .chain(pathext.iter().map(|ext| format!("{}{}", command, ext)))
+        .collect()
+}
+
+/// The files in `dir`, keyed by lowercase file name (Windows file names are case-insensitive).
+fn index_dir(system: &impl DiscoveryOps, dir: &Path) -> HashMap<String, PathBuf> {
+    system.list_dir(dir).into_iter()
+        .filter(|p| system.is_file(p))
+        .filter_map(|p| Some((p.file_name()?.to_string_lossy().to_lowercase(), p)))
+        .collect()
+}
+
+/// Lists the files in each search directory once, for resolving many commands.
+pub struct DirIndex {
+    files: Vec<HashMap<String, PathBuf>>,
+}
+
+impl DirIndex {
+    pub fn new(system: &impl DiscoveryOps, dirs: &[SearchDir]) -> Self {
+        Self { files: dirs.iter().map(|d| index_dir(system, &d.dir)).collect() }
+    }
+
+    /// Every file `command` resolves to, best first: directory order, then `PATHEXT` order.
+    pub fn hits(&self, dirs: &[SearchDir], command: &str, pathext: &[String]) -> Vec<Hit> {
+        let names = file_names(command, pathext);
+        let mut hits = Vec::new();
+        for (dir, files) in dirs.iter().zip(&self.files) {
+            for name in &names {
+                if let Some(file) = files.get(name) {
+                    hits.push(Hit { scope: dir.scope, entry: dir.entry.clone(), file: file.clone() });
+                }
+            }
+        }
+        hits
+    }
+}
+
+/// Finds every command that resolves to more than one file on the PATH.
+///
+/// `commands` are bare names (e.g. the keys of the `discovery` candidate map).
+/// The result is sorted by command name.
+pub fn find_shadows<'a>(
+    system: &impl DiscoveryOps,
+    dirs: &[SearchDir],
+    commands: impl IntoIterator<Item = &'a String>,
+    pathext: &[String],
+) -> Vec<Shadow> {
+    let index = DirIndex::new(system, dirs);
+    let commands: BTreeSet<&String> = commands.into_iter().collect();
+    commands.into_iter()
+        .filter_map(|command| {
+            let mut hits = index.hits(dirs, command, pathext).into_iter();
+            let winner = hits.next()?;
+            let shadowed: Vec<Hit> = hits.collect();
+            (!shadowed.is_empty()).then(|| Shadow { command: command.clone(), winner, shadowed })
+        })
+        .collect()
+}
+
+/// Where a `which` match came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// The current directory, searched first by cmd.exe.
+    CurrentDir,
+    /// A PATH entry (as written in the PATH value).
+    Path { scope: Scope, entry: String },
+    /// An `App Paths` registry entry: only used by the shell, and only if nothing else matches.
+    AppPaths(Hive),
+    /// Found by discovery in a directory that is not on the PATH, so it never runs.
+    Discovered { source: String },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::CurrentDir => write!(f, "current directory"),
+            Origin::Path { scope, entry } => write!(f, "{:?} PATH: {}", scope, entry),
+            Origin::AppPaths(Hive::CurrentUser) => write!(f, "HKCU App Paths (Start menu / Run only)"),
+            Origin::AppPaths(Hive::LocalMachine) => write!(f, "HKLM App Paths (Start menu / Run only)"),
+            Origin::Discovered { source } => write!(f, "found by {}, not on PATH", source),
+        }
+    }
+}
+
+/// One file a command name could resolve to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub origin: Origin,
+    pub file: PathBuf,
+}
+
+impl Match {
+    /// Can this match ever run? Discovered-only matches cannot.
+    pub fn reachable(&self) -> bool {
+        !matches!(self.origin, Origin::Discovered { .. })
+    }
+}
+
+/// Every file `command` could resolve to, in the order Windows tries them.
+///
+/// The first reachable match is the one that runs. `cwd` is the current directory; it is
+/// skipped if `NoDefaultCurrentDirectoryInExePath` is set, as Windows does. `candidates` is
+/// the discovery map, used to list copies that are installed but not on the PATH.
+pub fn which(
+    system: &impl DiscoveryOps,
+    dirs: &[SearchDir],
+    cwd: Option<&Path>,
+    candidates: &HashMap<String, Vec<Candidate>>,
+    command: &str,
+    pathext: &[String],
+) -> Vec<Match> {
+    let names = file_names(command, pathext);
+    let mut matches = Vec::new();
+
+    if let Some(cwd) = cwd.filter(|_| system.env_var("NoDefaultCurrentDirectoryInExePath").is_none()) {
+        let files = index_dir(system, cwd);
+        for name in &names {
+            if let Some(file) = files.get(name) {
+                matches.push(Match { origin: Origin::CurrentDir, file: file.clone() });
+            }
+        }
+    }
+
+    for hit in DirIndex::new(system, dirs).hits(dirs, command, pathext) {
+        matches.push(Match { origin: Origin::Path { scope: hit.scope, entry: hit.entry }, file: hit.file });
+    }
+
+    // The shell looks up `App Paths\<name>.exe`, adding `.exe` if the name has no extension
+    let exe = match Path::new(command).extension() {
+        Some(_) => command.to_string(),
+        None => format!("{}.exe", command),
+    };
+    for hive in [Hive::CurrentUser, Hive::LocalMachine] {
+        let registered = system.registry_string(hive, &format!(r"{}\{}", APP_PATHS, exe), "")
+            .map(|value| PathBuf::from(value.trim().trim_matches('"')))
+            .filter(|file| system.is_file(file));
+        if let Some(file) = registered {
+            matches.push(Match { origin: Origin::AppPaths(hive), file });
+        }
+    }
+
+    // Discovered copies in directories the search above never looks at
+    let searched: HashSet<String> = dirs.iter()
+        .map(|d| normalize::canonicalize(&d.dir.to_string_lossy(), |_| None))
+        .chain(cwd.map(|cwd| normalize::canonicalize(&cwd.to_string_lossy(), |_| None)))
+        .collect();
+    let stem = Path::new(command).file_stem().map(|s| s.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let mut reported = HashSet::new();
+    for candidate in candidates.get(&stem).into_iter().flatten() {
+        let key = normalize::canonicalize(&candidate.path.to_string_lossy(), |_| None);
+        if searched.contains(&key) || !reported.insert(key) {
+            continue;
+        }
+        let files = index_dir(system, &candidate.path);
+        if let Some(file) = names.iter().find_map(|name| files.get(name)) {
+            matches.push(Match { origin: Origin::Discovered { source: candidate.source.clone() }, file: file.clone() });
+        }
+    }
+
+    matches
+}
+
+/// Prints how `command` resolves, with the winner marked. Returns false if it resolves to nothing.
+pub fn print_which(
+    system: &(impl SystemOps + DiscoveryOps),
+    cwd: Option<&Path>,
+    candidates: &HashMap<String, Vec<Candidate>>,
+    command: &str,
+) -> bool {
+    let matches = which(system, &search_dirs(system), cwd, candidates, command, &pathext(system));
+    let winner = matches.iter().position(Match::reachable);
+    let shims = discovery::chocolatey_shims(system);
+
+    if matches.is_empty() {
+        println!("{}: not found", command);
+    }
+    for (i, m) in matches.iter().enumerate() {
+        let marker = if Some(i) == winner {
+            "✓"
+        } else if m.reachable() {
+            "✕"
+        } else {
+            "·"
+        };
+        match shims.get(&normalize::canonicalize(&m.file.to_string_lossy(), |_| None)) {
+            Some(target) => println!("{} {} (shim for {})  ({})", marker, m.file.display(), target.display(), m.origin),
+            None => println!("{} {}  ({})", marker, m.file.display(), m.origin),
+        }
+    }
+    if winner.is_none() && !matches.is_empty() {
+        println!();
+        println!("{} is installed but not on the PATH; run 'wanderlust heal' to add it.", command);
+    }
+    winner.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    fn fixture() -> MockSystem {
+        let system = MockSystem::new();
+        system.write_system_path_registry("/sys/bin;/sys/tools").unwrap();
+        system.write_user_path_registry("/home/me/WindowsApps;%TOOLS%;/SYS/BIN/").unwrap();
+        system.set_env("TOOLS", "/home/me/tools");
+        system
+    }
+
+    #[test]
+    fn test_pathext_defaults_and_order() {
+        let system = MockSystem::new();
+        assert_eq!(&pathext(&system)[..3], [".com", ".exe", ".bat"]);
+
+        system.set_env("PATHEXT", ".CMD;.EXE;;exe;.cmd");
+        assert_eq!(pathext(&system), vec![".cmd", ".exe"]);
+    }
+
+    #[test]
+    fn test_search_dirs_system_first_and_deduped() {
+        let system = fixture();
+        let dirs: Vec<(Scope, String)> = search_dirs(&system).into_iter()
+            .map(|d| (d.scope, d.dir.to_string_lossy().to_string()))
+            .collect();
+
+        assert_eq!(dirs, vec![
+            (Scope::System, "/sys/bin".to_string()),
+            (Scope::System, "/sys/tools".to_string()),
+            (Scope::User, "/home/me/WindowsApps".to_string()),
+            (Scope::User, "/home/me/tools".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_find_shadows_honours_dir_and_pathext_order() {
+        let system = fixture();
+        system.add_file("/home/me/WindowsApps/python.exe");
+        system.add_file("/home/me/tools/python.exe");
+        system.add_file("/sys/tools/node.cmd");
+        system.add_file("/sys/tools/node.EXE");
+        system.add_file("/sys/bin/git.exe");
+        let commands = ["python", "node", "git"].map(str::to_string);
+
+        let shadows = find_shadows(&system, &search_dirs(&system), &commands, &pathext(&system));
+
+        assert_eq!(shadows.len(), 2, "git is unique: {:?}", shadows);
+        assert_eq!(shadows[0].command, "node");
+        assert_eq!(shadows[0].winner.file, PathBuf::from("/sys/tools/node.EXE"), ".EXE precedes .CMD in PATHEXT");
+        assert_eq!(shadows[0].shadowed[0].file, PathBuf::from("/sys/tools/node.cmd"));
+        assert_eq!(shadows[1].command, "python");
+        assert_eq!(shadows[1].winner.file, PathBuf::from("/home/me/WindowsApps/python.exe"));
+        assert_eq!(shadows[1].shadowed[0].entry, "%TOOLS%");
+        assert_eq!(shadows[1].shadowed[0].scope, Scope::User);
+    }
+
+    #[test]
+    fn test_which_lists_every_candidate_in_windows_order() {
+        let system = fixture();
+        system.add_file("/work/tool.bat");
+        system.add_file("/sys/tools/tool.exe");
+        system.add_file("/home/me/tools/TOOL.CMD");
+        system.add_file("/opt/tool/tool.exe");
+        system.add_file("/opt/unlisted/tool.exe");
+        system.set_registry_string(Hive::LocalMachine, &format!(r"{}\tool.exe", APP_PATHS), "", r#""/opt/tool/tool.exe""#);
+        let candidates = HashMap::from([("tool".to_string(), vec![
+            Candidate { path: PathBuf::from("/opt/unlisted"), source: "scoop".to_string() },
+            Candidate { path: PathBuf::from("/sys/tools"), source: "existing_path".to_string() },
+        ])]);
+
+        let resolve = |cwd| which(&system, &search_dirs(&system), cwd, &candidates, "tool", &pathext(&system));
+
+        let origins: Vec<Origin> = resolve(Some(Path::new("/work"))).into_iter().map(|m| m.origin).collect();
+        assert_eq!(origins, vec![
+            Origin::CurrentDir,
+            Origin::Path { scope: Scope::System, entry: "/sys/tools".to_string() },
+            Origin::Path { scope: Scope::User, entry: "%TOOLS%".to_string() },
+            Origin::AppPaths(Hive::LocalMachine),
+            Origin::Discovered { source: "scoop".to_string() },
+        ]);
+
+        system.set_env("NoDefaultCurrentDirectoryInExePath", "1");
+        let matches = resolve(Some(Path::new("/work")));
+        assert_eq!(matches[0].file, PathBuf::from("/sys/tools/tool.exe"), "cwd is skipped");
+        assert!(!matches.last().unwrap().reachable());
+    }
+
+    #[test]
+    fn test_which_with_explicit_extension() {
+        let system = fixture();
+        system.add_file("/sys/bin/node.cmd");
+        system.add_file("/sys/bin/node.cmd.exe");
+        system.add_file("/sys/bin/node.exe");
+
+        let files: Vec<PathBuf> = which(&system, &search_dirs(&system), None, &HashMap::new(), "node.cmd", &pathext(&system))
+            .into_iter()
+            .map(|m| m.file)
+            .collect();
+
+        assert_eq!(files, vec![PathBuf::from("/sys/bin/node.cmd"), PathBuf::from("/sys/bin/node.cmd.exe")]);
+    }
+}
@@ -0,0 +1,166 @@
+//! # Persistent State
+//!
+//! What Wanderlust remembers between heals, in `%LOCALAPPDATA%\wanderlust\state.json`.
+//!
+//! Currently that is which PATH entries pointed at a missing directory, and since when.
+//! A directory on a removable drive or VPN share is only removed once it has been gone
+//! for a while (see `config::DeadEntries`), so a heal that runs while the laptop is off
+//! the network does not purge the share from the PATH.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::config::DeadEntries;
+use crate::system::SystemOps;
+
+pub(crate) const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// One entry that has been missing on consecutive heals.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingEntry {
+    /// Unix time of the first heal in the current streak that found it missing.
+    pub first_missing: u64,
+    /// Unix time of the latest heal that found it missing.
+    pub last_missing: u64,
+    /// On how many days (UTC) a heal found it missing, without a heal finding it in between.
+    pub runs: u32,
+}
+
+/// State carried from one heal to the next.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealState {
+    /// Entries whose directory was missing on the latest heal, by canonical key
+    /// (see `normalize::canonicalize`).
+    #[serde(default)]
+    pub missing: BTreeMap<String, MissingEntry>,
+}
+
+/// Where the state file lives.
+pub fn state_path(system: &impl SystemOps) -> Option<PathBuf> {
+    system.app_data_dir().map(|dir| dir.join("state.json"))
+}
+
+impl HealState {
+    /// Reads the state file. A missing or unreadable file means a fresh state:
+    /// the worst that can happen is that dead entries survive a few more heals.
+    pub fn load(system: &impl SystemOps) -> Self {
+        let Some(path) = state_path(system) else {
+            return Self::default();
+        };
+        if !system.path_exists(&path) {
+            return Self::default();
+        }
+        match system.read_data_file(&path).and_then(|json| Ok(serde_json::from_str(&json)?)) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Ignoring unreadable state file {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Writes the state file.
+    pub fn save(&self, system: &impl SystemOps) -> Result<()> {
+        let path = state_path(system).context("Could not determine the state file location")?;
+        system.write_data_file(&path, &serde_json::to_string_pretty(self)?)
+    }
+
+    /// Records one heal that found exactly the entries in `missing` (canonical keys) missing
+    /// at Unix time `now`. Entries that are no longer missing start over from zero.
+    ///
+    /// Only the first miss of each day counts as a run: the scheduled heal runs every
+    /// 30 minutes, and a share that is gone for an afternoon is not dead.
+    pub fn observe(&mut self, missing: impl IntoIterator<Item = String>, now: u64) {
+        let mut observed = BTreeMap::new();
+        for key in missing {
+            let entry = self.missing.remove(&key)
+                .map(|e| {
+                    let new_day = now / SECONDS_PER_DAY > e.last_missing / SECONDS_PER_DAY;
+                    MissingEntry { last_missing: now, runs: e.runs + u32::from(new_day), ..e }
+                })
+                .unwrap_or(MissingEntry { first_missing: now, last_missing: now, runs: 1 });
+            observed.entry(key).or_insert(entry);
+        }
+        self.missing = observed;
+    }
+
+    /// Has the entry with canonical key `key` been missing long enough to be removed?
+    pub fn is_dead(&self, key: &str, policy: &DeadEntries) -> bool {
+        self.missing.get(key).is_some_and(|e| {
+            let days = e.last_missing.saturating_sub(e.first_missing) / SECONDS_PER_DAY;
+            e.runs >= policy.grace_runs || days >= policy.grace_days
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    #[test]
+    fn test_observe_counts_consecutive_misses() {
+        let policy = DeadEntries { grace_runs: 3, grace_days: 7 };
+        let mut state = HealState::default();
+
+        state.observe([r"e:\tools".to_string(), r"\\vpn\share\bin".to_string()], 0);
+        state.observe([r"e:\tools".to_string(), r"\\vpn\share\bin".to_string()], SECONDS_PER_DAY);
+        assert!(!state.is_dead(r"e:\tools", &policy));
+
+        // The share came back, so its streak is over
+        state.observe([r"e:\tools".to_string()], 2 * SECONDS_PER_DAY);
+        assert!(state.is_dead(r"e:\tools", &policy));
+        assert!(!state.missing.contains_key(r"\\vpn\share\bin"));
+
+        state.observe([r"\\vpn\share\bin".to_string()], 3 * SECONDS_PER_DAY);
+        assert_eq!(state.missing[r"\\vpn\share\bin"], MissingEntry { first_missing: 3 * SECONDS_PER_DAY, last_missing: 3 * SECONDS_PER_DAY, runs: 1 });
+        assert!(!state.is_dead(r"e:\tools", &policy), "Forgotten once it reappeared");
+    }
+
+    #[test]
+    fn test_observe_counts_one_run_per_day() {
+        let policy = DeadEntries::default();
+        let mut state = HealState::default();
+
+        // A scheduled heal every 30 minutes for a whole day
+        for half_hour in 0..48 {
+            state.observe([r"\\vpn\share\bin".to_string()], half_hour * 30 * 60);
+        }
+        assert_eq!(state.missing[r"\\vpn\share\bin"].runs, 1);
+        assert!(!state.is_dead(r"\\vpn\share\bin", &policy), "Gone for a day is not dead");
+
+        state.observe([r"\\vpn\share\bin".to_string()], SECONDS_PER_DAY);
+        state.observe([r"\\vpn\share\bin".to_string()], 2 * SECONDS_PER_DAY);
+        assert!(state.is_dead(r"\\vpn\share\bin", &policy), "Missing on three days");
+    }
+
+    #[test]
+    fn test_is_dead_after_grace_days() {
+        let policy = DeadEntries { grace_runs: 10, grace_days: 7 };
+        let mut state = HealState::default();
+
+        state.observe([r"e:\tools".to_string()], 0);
+        state.observe([r"e:\tools".to_string()], 6 * SECONDS_PER_DAY);
+        assert!(!state.is_dead(r"e:\tools", &policy));
+
+        state.observe([r"e:\tools".to_string()], 7 * SECONDS_PER_DAY);
+        assert!(state.is_dead(r"e:\tools", &policy));
+        assert!(!state.is_dead(r"c:\never\missing", &policy));
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let system = MockSystem::new();
+        assert_eq!(HealState::load(&system), HealState::default());
+
+        let mut state = HealState::default();
+        state.observe([r"e:\tools".to_string()], 42);
+        state.save(&system).unwrap();
+        assert_eq!(HealState::load(&system), state);
+
+        system.write_data_file(&state_path(&system).unwrap(), "{ not json").unwrap();
+        assert_eq!(HealState::load(&system), HealState::default());
+    }
+}
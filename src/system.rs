@@ -1,50 +1,206 @@
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::normalize;
+use crate::plan::Scope;
+#[cfg(windows)]
 use windows_registry::{CURRENT_USER, LOCAL_MACHINE};
 
+/// The Registry type of a PATH value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueKind {
+    /// `REG_SZ`: stored and used literally.
+    #[default]
+    String,
+    /// `REG_EXPAND_SZ`: `%VAR%` references are expanded when Windows builds the environment.
+    ExpandString,
+}
+
+/// A raw PATH value exactly as stored in the Registry: never expanded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathValue {
+    pub data: String,
+    pub kind: ValueKind,
+}
+
+impl PathValue {
+    /// The value to write when replacing a PATH whose current type is `current`.
+    ///
+    /// `REG_EXPAND_SZ` is never downgraded, and any `%VAR%` reference forces it, so
+    /// entries like `%USERPROFILE%\.cargo\bin` keep expanding. A missing value gets
+    /// `REG_EXPAND_SZ`, the Windows default for PATH.
+    pub fn replacing(current: Option<ValueKind>, data: &str) -> Self {
+        let kind = match current {
+            Some(ValueKind::String) if !data.contains('%') => ValueKind::String,
+            _ => ValueKind::ExpandString,
+        };
+        Self { data: data.to_string(), kind }
+    }
+}
+
 /// Abstraction for System interactions (Registry, File System, Environment).
 /// This allows us to mock the dangerous Windows Registry interactions for testing.
+///
+/// PATH values are read and written raw, together with their Registry type (`PathValue`).
+/// The `*_path_registry` string helpers are built on top and preserve that type.
 pub trait SystemOps {
-    /// Read the current PATH from the Registry (User scope).
-    fn read_user_path_registry(&self) -> Result<String>;
-    
-    /// Write the new PATH to the Registry (User scope).
-    fn write_user_path_registry(&self, new_path: &str) -> Result<()>;
-    
+    /// Read the raw PATH value and its type from the Registry.
+    fn read_path_value(&self, scope: Scope) -> Result<PathValue>;
+
+    /// Write a raw PATH value with exactly the given type.
+    /// The System scope (HKLM) requires Admin privileges.
+    fn write_path_value(&self, scope: Scope, value: &PathValue) -> Result<()>;
+
+    /// Look up an environment variable used to expand `%VAR%` references in PATH entries.
+    fn lookup_env_var(&self, name: &str) -> Option<String>;
+
+    /// Read a variable of the User environment (`HKCU\Environment`), unexpanded.
+    fn read_user_env_var(&self, name: &str) -> Option<String>;
+
+    /// Write a variable of the User environment (`HKCU\Environment`) as `REG_SZ`.
+    fn write_user_env_var(&self, name: &str, value: &str) -> Result<()>;
+
+    /// Expand `%VAR%` references the way Windows would. Unknown variables are left as-is.
+    fn expand_env_vars(&self, value: &str) -> String {
+        normalize::expand_env_vars(value, |name| self.lookup_env_var(name))
+    }
+
     /// Broadcast the "Environment Changed" message to the system.
     fn broadcast_environment_change(&self) -> Result<()>;
     
     /// Check if a directory exists on the file system.
     fn path_exists(&self, path: &Path) -> bool;
 
-    /// Write a backup file to disk.
-    fn write_backup_file(&self, path: &Path, content: &str) -> Result<()>;
+    /// Write one of Wanderlust's own files (backups, state), creating its parent directory if needed.
+    fn write_data_file(&self, path: &Path, content: &str) -> Result<()>;
+
+    /// Read a file previously written with `write_data_file`.
+    fn read_data_file(&self, path: &Path) -> Result<String>;
+
+    /// List the files directly inside one of Wanderlust's directories (e.g. the backups).
+    fn list_data_files(&self, dir: &Path) -> Vec<PathBuf>;
+
+    /// Delete one of Wanderlust's own files (e.g. backup retention pruning).
+    fn remove_data_file(&self, path: &Path) -> Result<()>;
+
+    /// Wanderlust's own data directory (`%LOCALAPPDATA%\wanderlust`), if it can be determined.
+    fn app_data_dir(&self) -> Option<PathBuf>;
 
     /// Run system verification probes (cmd, powershell) to ensure PATH is valid.
     fn verify_environment_health(&self) -> bool;
 
-    /// Read the System PATH from the Registry (Machine scope - HKLM).
-    fn read_system_path_registry(&self) -> Result<String>;
+    /// Read the current PATH from the Registry (User scope), unexpanded.
+    fn read_user_path_registry(&self) -> Result<String> {
+        self.read_path_registry(Scope::User)
+    }
+    
+    /// Write the new PATH to the Registry (User scope), preserving its type.
+    fn write_user_path_registry(&self, new_path: &str) -> Result<()> {
+        self.write_path_registry(Scope::User, new_path)
+    }
+
+    /// Read the System PATH from the Registry (Machine scope - HKLM), unexpanded.
+    fn read_system_path_registry(&self) -> Result<String> {
+        self.read_path_registry(Scope::System)
+    }
 
-    /// Write the System PATH to the Registry (Machine scope - HKLM).
+    /// Write the System PATH to the Registry (Machine scope - HKLM), preserving its type.
     /// Requires Admin privileges.
-    fn write_system_path_registry(&self, new_path: &str) -> Result<()>;
+    fn write_system_path_registry(&self, new_path: &str) -> Result<()> {
+        self.write_path_registry(Scope::System, new_path)
+    }
+
+    /// Read the PATH of either scope, unexpanded.
+    fn read_path_registry(&self, scope: Scope) -> Result<String> {
+        Ok(self.read_path_value(scope)?.data)
+    }
+
+    /// Write the PATH of either scope, keeping (or upgrading to) `REG_EXPAND_SZ` as needed.
+    fn write_path_registry(&self, scope: Scope, new_path: &str) -> Result<()> {
+        let current = self.read_path_value(scope).ok().map(|v| v.kind);
+        self.write_path_value(scope, &PathValue::replacing(current, new_path))
+    }
+}
+
+/// A Registry root that discovery is allowed to read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hive {
+    /// `HKEY_CURRENT_USER`
+    CurrentUser,
+    /// `HKEY_LOCAL_MACHINE`
+    LocalMachine,
+}
+
+/// Read-only abstraction over everything the discovery phase looks at.
+///
+/// Discovery never writes, so this is kept separate from `SystemOps`.
+/// Implementations swallow errors: a key or directory that can't be read
+/// simply yields nothing.
+pub trait DiscoveryOps {
+    /// List the names of the subkeys directly below `hive\path`.
+    fn registry_subkeys(&self, hive: Hive, path: &str) -> Vec<String>;
+
+    /// Read a string value `name` from the key `hive\path`. DWORD values are returned in decimal.
+    fn registry_string(&self, hive: Hive, path: &str, name: &str) -> Option<String>;
+
+    /// List the immediate children (files and directories) of `dir`.
+    fn list_dir(&self, dir: &Path) -> Vec<PathBuf>;
+
+    /// Check if `path` is an existing directory.
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Check if `path` is an existing regular file.
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Look up a process environment variable (e.g. `PATH`).
+    fn env_var(&self, name: &str) -> Option<String>;
+
+    /// The current user's profile directory (`%USERPROFILE%`).
+    fn home_dir(&self) -> Option<PathBuf>;
 }
 
 /// The Real System implementation (Production).
+///
+/// Only compiled on Windows; every other platform uses `MockSystem`.
+#[cfg(windows)]
 pub struct WindowsSystem;
 
+#[cfg(windows)]
 impl SystemOps for WindowsSystem {
-    fn read_user_path_registry(&self) -> Result<String> {
-        let key = CURRENT_USER.open("Environment")?;
-        let path_val = key.get_string("Path")?;
-        Ok(path_val)
+    fn read_path_value(&self, scope: Scope) -> Result<PathValue> {
+        let (hive, path) = Self::path_key(scope);
+        let key = hive.open(path)?;
+        let kind = match key.get_type("Path")? {
+            windows_registry::Type::ExpandString => ValueKind::ExpandString,
+            _ => ValueKind::String,
+        };
+        // get_string returns the raw data; REG_EXPAND_SZ is NOT expanded here.
+        let data = key.get_string("Path")?;
+        Ok(PathValue { data, kind })
     }
 
-    fn write_user_path_registry(&self, new_path: &str) -> Result<()> {
-        let key = CURRENT_USER.create("Environment")?;
-        key.set_string("Path", new_path)?;
+    fn write_path_value(&self, scope: Scope, value: &PathValue) -> Result<()> {
+        let (hive, path) = Self::path_key(scope);
+        let key = hive.create(path)?;
+        match value.kind {
+            ValueKind::String => key.set_string("Path", &value.data)?,
+            ValueKind::ExpandString => key.set_expand_string("Path", &value.data)?,
+        }
+        Ok(())
+    }
+
+    fn lookup_env_var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn read_user_env_var(&self, name: &str) -> Option<String> {
+        CURRENT_USER.open("Environment").ok()?.get_string(name).ok()
+    }
+
+    fn write_user_env_var(&self, name: &str, value: &str) -> Result<()> {
+        CURRENT_USER.create("Environment")?.set_string(name, value)?;
         Ok(())
     }
 
@@ -72,13 +228,34 @@ impl SystemOps for WindowsSystem {
         path.exists()
     }
 
-    fn write_backup_file(&self, path: &Path, content: &str) -> Result<()> {
+    fn write_data_file(&self, path: &Path, content: &str) -> Result<()> {
         use std::io::Write;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
         let mut f = std::fs::File::create(path)?;
         f.write_all(content.as_bytes())?;
         Ok(())
     }
 
+    fn read_data_file(&self, path: &Path) -> Result<String> {
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    fn list_data_files(&self, dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_file()).collect())
+            .unwrap_or_default()
+    }
+
+    fn remove_data_file(&self, path: &Path) -> Result<()> {
+        Ok(std::fs::remove_file(path)?)
+    }
+
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        directories::BaseDirs::new().map(|dirs| dirs.data_local_dir().join("wanderlust"))
+    }
+
     fn verify_environment_health(&self) -> bool {
         let probes = vec![
             "cmd.exe /C ver",
@@ -101,26 +278,100 @@ impl SystemOps for WindowsSystem {
     
         success_count >= 2
     }
+}
 
-    fn read_system_path_registry(&self) -> Result<String> {
-        let key = LOCAL_MACHINE.open(r"SYSTEM\CurrentControlSet\Control\Session Manager\Environment")?;
-        let path_val = key.get_string("Path")?;
-        Ok(path_val)
+#[cfg(windows)]
+impl WindowsSystem {
+    /// Where each scope's PATH lives.
+    fn path_key(scope: Scope) -> (&'static windows_registry::Key, &'static str) {
+        match scope {
+            Scope::User => (CURRENT_USER, "Environment"),
+            Scope::System => (LOCAL_MACHINE, r"SYSTEM\CurrentControlSet\Control\Session Manager\Environment"),
+        }
     }
 
-    fn write_system_path_registry(&self, new_path: &str) -> Result<()> {
-        let key = LOCAL_MACHINE.create(r"SYSTEM\CurrentControlSet\Control\Session Manager\Environment")?;
-        key.set_string("Path", new_path)?;
-        Ok(())
+    fn hive_key(hive: Hive) -> &'static windows_registry::Key {
+        match hive {
+            Hive::CurrentUser => CURRENT_USER,
+            Hive::LocalMachine => LOCAL_MACHINE,
+        }
+    }
+}
+
+#[cfg(windows)]
+impl DiscoveryOps for WindowsSystem {
+    fn registry_subkeys(&self, hive: Hive, path: &str) -> Vec<String> {
+        Self::hive_key(hive).open(path)
+            .and_then(|key| key.keys().map(|keys| keys.collect()))
+            .unwrap_or_default()
+    }
+
+    fn registry_string(&self, hive: Hive, path: &str, name: &str) -> Option<String> {
+        let key = Self::hive_key(hive).open(path).ok()?;
+        key.get_string(name).ok()
+            .or_else(|| key.get_u32(name).ok().map(|v| v.to_string()))
+    }
+
+    fn list_dir(&self, dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+            .unwrap_or_default()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        directories::UserDirs::new().map(|dirs| dirs.home_dir().to_path_buf())
     }
 }
 
 /// A Mock System for Testing.
+///
+/// `file_system` holds files, `directories` holds directories. Use `add_file`/`add_dir`
+/// to populate them so parent directories are created implicitly.
 #[derive(Debug, Default)]
 pub struct MockSystem {
     pub registry: std::sync::Mutex<HashMap<String, String>>,
     pub file_system: std::sync::Mutex<Vec<PathBuf>>,
     pub broadcast_called: std::sync::Mutex<bool>,
+    pub directories: std::sync::Mutex<BTreeSet<PathBuf>>,
+    /// Registry keys for discovery: `(hive, key path) -> (value name -> value)`.
+    pub registry_keys: std::sync::Mutex<HashMap<(Hive, String), HashMap<String, String>>>,
+    pub env: std::sync::Mutex<HashMap<String, String>>,
+    pub home: std::sync::Mutex<Option<PathBuf>>,
+    /// Contents of files written through `write_data_file`.
+    pub file_contents: std::sync::Mutex<HashMap<PathBuf, String>>,
+    /// When set, `verify_environment_health` fails (to exercise rollback).
+    pub unhealthy: std::sync::Mutex<bool>,
+    /// Registry type of the `registry` entries. Missing means `REG_SZ`.
+    pub registry_kinds: std::sync::Mutex<HashMap<String, ValueKind>>,
+    /// Variables of the User environment other than PATH (`HKCU\Environment`).
+    pub user_env: std::sync::Mutex<HashMap<String, String>>,
+    /// When set, writing the System PATH fails, as it does without Administrator rights.
+    pub system_read_only: std::sync::Mutex<bool>,
+}
+
+impl MockSystem {
+    /// The fixed `app_data_dir` reported by the mock.
+    pub const APP_DATA_DIR: &'static str = "/mock/AppData/Local/wanderlust";
+
+    /// The `registry` map key used for each scope's PATH.
+    fn registry_name(scope: Scope) -> &'static str {
+        match scope {
+            Scope::User => "Path",
+            Scope::System => "SystemPath",
+        }
+    }
 }
 
 impl MockSystem {
@@ -138,19 +389,85 @@ impl MockSystem {
             ..Default::default()
         }
     }
+
+    /// Add a file, creating all of its parent directories.
+    #[allow(dead_code)]
+    pub fn add_file(&self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            self.add_dir(parent);
+        }
+        self.file_system.lock().unwrap().push(path);
+    }
+
+    /// Add a directory and all of its ancestors.
+    #[allow(dead_code)]
+    pub fn add_dir(&self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        let mut dirs = self.directories.lock().unwrap();
+        for ancestor in path.ancestors().filter(|a| !a.as_os_str().is_empty()) {
+            dirs.insert(ancestor.to_path_buf());
+        }
+    }
+
+    /// Set a string value on a (discovery) registry key, creating the key if needed.
+    #[allow(dead_code)]
+    pub fn set_registry_string(&self, hive: Hive, path: &str, name: &str, value: &str) {
+        self.registry_keys.lock().unwrap()
+            .entry((hive, path.to_string()))
+            .or_default()
+            .insert(name.to_string(), value.to_string());
+    }
+
+    /// Set a process environment variable.
+    #[allow(dead_code)]
+    pub fn set_env(&self, name: &str, value: &str) {
+        self.env.lock().unwrap().insert(name.to_string(), value.to_string());
+    }
+
+    /// Set the user's profile directory (and create it).
+    #[allow(dead_code)]
+    pub fn set_home(&self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.add_dir(path.clone());
+        *self.home.lock().unwrap() = Some(path);
+    }
 }
 
 impl SystemOps for MockSystem {
-    fn read_user_path_registry(&self) -> Result<String> {
+    fn read_path_value(&self, scope: Scope) -> Result<PathValue> {
+        let name = Self::registry_name(scope);
         let map = self.registry.lock().unwrap();
-        map.get("Path")
+        let data = map.get(name)
            .cloned()
-           .ok_or_else(|| anyhow::anyhow!("Path not found in mock registry"))
+           .ok_or_else(|| anyhow::anyhow!("{} not found in mock registry", name))?;
+        let kind = self.registry_kinds.lock().unwrap().get(name).copied().unwrap_or_default();
+        Ok(PathValue { data, kind })
     }
 
-    fn write_user_path_registry(&self, new_path: &str) -> Result<()> {
-        let mut map = self.registry.lock().unwrap();
-        map.insert("Path".to_string(), new_path.to_string());
+    fn write_path_value(&self, scope: Scope, value: &PathValue) -> Result<()> {
+        if scope == Scope::System && *self.system_read_only.lock().unwrap() {
+            anyhow::bail!("Access is denied");
+        }
+        let name = Self::registry_name(scope);
+        self.registry.lock().unwrap().insert(name.to_string(), value.data.clone());
+        self.registry_kinds.lock().unwrap().insert(name.to_string(), value.kind);
+        Ok(())
+    }
+
+    fn lookup_env_var(&self, name: &str) -> Option<String> {
+        // Windows environment variable names are case-insensitive
+        let env = self.env.lock().unwrap();
+        env.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone())
+    }
+
+    fn read_user_env_var(&self, name: &str) -> Option<String> {
+        let env = self.user_env.lock().unwrap();
+        env.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone())
+    }
+
+    fn write_user_env_var(&self, name: &str, value: &str) -> Result<()> {
+        self.user_env.lock().unwrap().insert(name.to_string(), value.to_string());
         Ok(())
     }
 
@@ -162,29 +479,87 @@ impl SystemOps for MockSystem {
 
     fn path_exists(&self, path: &Path) -> bool {
         let fs = self.file_system.lock().unwrap();
-        fs.contains(&path.to_path_buf())
+        fs.contains(&path.to_path_buf()) || self.directories.lock().unwrap().contains(path)
     }
 
-    fn write_backup_file(&self, path: &Path, _content: &str) -> Result<()> {
+    fn write_data_file(&self, path: &Path, content: &str) -> Result<()> {
         let mut fs = self.file_system.lock().unwrap();
-        fs.push(path.to_path_buf());
+        if !fs.contains(&path.to_path_buf()) {
+            fs.push(path.to_path_buf());
+        }
+        self.file_contents.lock().unwrap().insert(path.to_path_buf(), content.to_string());
         Ok(())
     }
 
-    fn verify_environment_health(&self) -> bool {
-        true
+    fn read_data_file(&self, path: &Path) -> Result<String> {
+        self.file_contents.lock().unwrap()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("{:?} not found in mock file system", path))
     }
 
-    fn read_system_path_registry(&self) -> Result<String> {
-        let map = self.registry.lock().unwrap();
-        map.get("SystemPath")
-           .cloned()
-           .ok_or_else(|| anyhow::anyhow!("SystemPath not found in mock registry"))
+    fn list_data_files(&self, dir: &Path) -> Vec<PathBuf> {
+        let fs = self.file_system.lock().unwrap();
+        fs.iter().filter(|p| p.parent() == Some(dir)).cloned().collect()
     }
 
-    fn write_system_path_registry(&self, new_path: &str) -> Result<()> {
-        let mut map = self.registry.lock().unwrap();
-        map.insert("SystemPath".to_string(), new_path.to_string());
+    fn remove_data_file(&self, path: &Path) -> Result<()> {
+        self.file_system.lock().unwrap().retain(|p| p != path);
+        self.file_contents.lock().unwrap().remove(path);
         Ok(())
     }
+
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        Some(PathBuf::from(Self::APP_DATA_DIR))
+    }
+
+    fn verify_environment_health(&self) -> bool {
+        !*self.unhealthy.lock().unwrap()
+    }
+}
+
+impl DiscoveryOps for MockSystem {
+    fn registry_subkeys(&self, hive: Hive, path: &str) -> Vec<String> {
+        let prefix = format!("{}\\", path.to_ascii_lowercase());
+        let keys = self.registry_keys.lock().unwrap();
+        let names: BTreeSet<String> = keys.keys()
+            .filter(|(h, k)| *h == hive && k.to_ascii_lowercase().starts_with(&prefix))
+            .filter_map(|(_, k)| k[prefix.len()..].split('\\').next().map(str::to_string))
+            .collect();
+        names.into_iter().collect()
+    }
+
+    fn registry_string(&self, hive: Hive, path: &str, name: &str) -> Option<String> {
+        let keys = self.registry_keys.lock().unwrap();
+        keys.iter()
+            .find(|((h, k), _)| *h == hive && k.eq_ignore_ascii_case(path))
+            .and_then(|(_, values)| values.get(name).cloned())
+    }
+
+    fn list_dir(&self, dir: &Path) -> Vec<PathBuf> {
+        let files = self.file_system.lock().unwrap();
+        let dirs = self.directories.lock().unwrap();
+        let children: BTreeSet<PathBuf> = files.iter()
+            .chain(dirs.iter())
+            .filter(|p| p.parent() == Some(dir))
+            .cloned()
+            .collect();
+        children.into_iter().collect()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.directories.lock().unwrap().contains(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.file_system.lock().unwrap().iter().any(|p| p == path)
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.env.lock().unwrap().get(name).cloned()
+    }
+
+    fn home_dir(&self) -> Option<PathBuf> {
+        self.home.lock().unwrap().clone()
+    }
 }
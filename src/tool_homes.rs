@@ -0,0 +1,140 @@
+//! # Known Tool Homes
+//!
+//! Developer toolchains install their commands into well-known per-user directories
+//! (`~\.cargo\bin`, `~\go\bin`, `%APPDATA%\npm`, ...). Discovery checks every directory in
+//! `DEFAULT_TOOL_HOMES`, plus any added in `config.toml`:
+//!
+//! ```toml
+//! [[tool_homes]]
+//! name = "corp"                  # the discovery source label
+//! path = '%USERPROFILE%\corp\bin'
+//! detect = "corp.exe"            # optional: only if this exists inside the directory
+//! ```
+//!
+//! A path starts with `~` (the home directory), a `%VAR%` reference or a drive. Paths
+//! whose variables are not set are skipped, so `%GOPATH%\bin` only counts where `GOPATH`
+//! is defined. A default home is switched off like any other source, in `[sources]`.
+
+use std::path::PathBuf;
+use serde::Deserialize;
+use crate::normalize;
+use crate::system::DiscoveryOps;
+
+/// The tool homes Wanderlust knows about: `(name, path, detect)`. Names are discovery
+/// source labels and may repeat when a tool has more than one possible home.
+pub const DEFAULT_TOOL_HOMES: &[(&str, &str, Option<&str>)] = &[
+    ("cargo", r"%CARGO_HOME%\bin", None),
+    ("cargo", r"~\.cargo\bin", None),
+    ("local_bin", r"~\.local\bin", None),
+    ("scoop", r"%SCOOP%\shims", None),
+    ("scoop", r"~\scoop\shims", None),
+    ("go", r"%GOPATH%\bin", None),
+    ("go", r"~\go\bin", None),
+    ("dotnet_tools", r"~\.dotnet\tools", None),
+    ("deno", r"%DENO_INSTALL%\bin", None),
+    ("deno", r"~\.deno\bin", None),
+    ("bun", r"%BUN_INSTALL%\bin", None),
+    ("bun", r"~\.bun\bin", None),
+    ("npm", r"%APPDATA%\npm", None),
+    ("pnpm", r"%PNPM_HOME%", None),
+    ("pnpm", r"%LOCALAPPDATA%\pnpm", Some("pnpm.exe")),
+    ("yarn", r"%LOCALAPPDATA%\Yarn\bin", None),
+    ("ghcup", r"%GHCUP_INSTALL_BASE_PREFIX%\ghcup\bin", None),
+    ("ghcup", r"C:\ghcup\bin", None),
+    ("ghcup", r"~\.ghcup\bin", None),
+    ("cabal", r"%APPDATA%\cabal\bin", None),
+    ("stack", r"%APPDATA%\local\bin", Some("stack.exe")),
+    ("juliaup", r"~\.juliaup\bin", None),
+    ("composer", r"%APPDATA%\Composer\vendor\bin", None),
+    ("dart_pub", r"%LOCALAPPDATA%\Pub\Cache\bin", None),
+    ("nimble", r"~\.nimble\bin", None),
+    ("mise", r"%LOCALAPPDATA%\mise\shims", None),
+];
+
+/// A directory a toolchain installs its commands into.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ToolHome {
+    /// The discovery source label for commands found here.
+    pub name: String,
+    /// Where it is, e.g. `~\go\bin` or `%APPDATA%\npm`.
+    pub path: String,
+    /// A file or directory (relative to `path`) that must exist for the home to count.
+    #[serde(default)]
+    pub detect: Option<String>,
+}
+
+impl ToolHome {
+    /// The directory this home points at on `system`, if its variables are set and it exists
+    /// (with its `detect` file, if any).
+    pub fn resolve(&self, system: &impl DiscoveryOps) -> Option<PathBuf> {
+        let mut segments = self.path.split(['\\', '/']).filter(|s| !s.is_empty());
+        let first = segments.next()?;
+        let mut dir = if first == "~" {
+            system.home_dir()?
+        } else {
+            let expanded = expand(system, first)?;
+            // `C:` alone is relative to the current directory on that drive
+            if expanded.ends_with(':') { PathBuf::from(format!(r"{}\", expanded)) } else { PathBuf::from(expanded) }
+        };
+        for segment in segments {
+            dir.push(expand(system, segment)?);
+        }
+        if !system.is_dir(&dir) {
+            return None;
+        }
+        match &self.detect {
+            Some(detect) => {
+                let marker = dir.join(detect);
+                (system.is_file(&marker) || system.is_dir(&marker)).then_some(dir)
+            }
+            None => Some(dir),
+        }
+    }
+}
+
+/// Expands `%VAR%` references in one path segment; `None` if a variable is not set.
+fn expand(system: &impl DiscoveryOps, segment: &str) -> Option<String> {
+    let expanded = normalize::expand_env_vars(segment, |name| system.env_var(name).filter(|v| !v.is_empty()));
+    (!expanded.contains('%')).then_some(expanded)
+}
+
+/// The default tool homes followed by the ones from `config.toml`.
+pub fn all(extra: &[ToolHome]) -> Vec<ToolHome> {
+    DEFAULT_TOOL_HOMES.iter()
+        .map(|&(name, path, detect)| ToolHome {
+            name: name.to_string(),
+            path: path.to_string(),
+            detect: detect.map(str::to_string),
+        })
+        .chain(extra.iter().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MockSystem;
+
+    fn home(path: &str, detect: Option<&str>) -> ToolHome {
+        ToolHome { name: "test".to_string(), path: path.to_string(), detect: detect.map(str::to_string) }
+    }
+
+    #[test]
+    fn test_resolve_templates() {
+        let system = MockSystem::new();
+        system.set_home("/home/me");
+        system.set_env("APPDATA", "/home/me/AppData/Roaming");
+        system.add_dir("/home/me/go/bin");
+        system.add_dir("/home/me/AppData/Roaming/npm");
+        system.add_file("/home/me/AppData/Roaming/local/bin/other.exe");
+
+        assert_eq!(home(r"~\go\bin", None).resolve(&system), Some(PathBuf::from("/home/me/go/bin")));
+        assert_eq!(home(r"%APPDATA%\npm", None).resolve(&system), Some(PathBuf::from("/home/me/AppData/Roaming/npm")));
+        assert_eq!(home(r"%GOPATH%\bin", None).resolve(&system), None, "GOPATH is not set");
+        assert_eq!(home(r"~\.deno\bin", None).resolve(&system), None, "Does not exist");
+        assert_eq!(home(r"%APPDATA%\local\bin", Some("stack.exe")).resolve(&system), None, "Detection file missing");
+
+        system.add_file("/home/me/AppData/Roaming/local/bin/stack.exe");
+        assert!(home(r"%APPDATA%\local\bin", Some("stack.exe")).resolve(&system).is_some());
+    }
+}
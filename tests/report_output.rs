@@ -0,0 +1,29 @@
+//! `doctor --format json` output must stay parseable when warnings are logged.
+//!
+//! The logger is process-wide, so this test runs itself as a child process (without the
+//! libtest harness, which prints to stdout) and parses what the child wrote to stdout.
+
+use std::collections::HashMap;
+use std::process::Command;
+use wanderlust::doctor::{self, ReportFormat};
+use wanderlust::system::MockSystem;
+
+const CHILD: &str = "WANDERLUST_REPORT_OUTPUT_CHILD";
+
+fn main() {
+    if std::env::var_os(CHILD).is_some() {
+        wanderlust::logging::init(log::LevelFilter::Info);
+        log::warn!("Ignoring unknown config key 'ordr'. Using the default config.");
+        let report = doctor::build_report(&MockSystem::new(), &HashMap::new());
+        print!("{}", report.render(ReportFormat::Json));
+        return;
+    }
+
+    let output = Command::new(std::env::current_exe().unwrap()).env(CHILD, "1").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(serde_json::from_str::<serde_json::Value>(&stdout).is_ok(), "Not JSON: {}", stdout);
+    assert!(stderr.contains("unknown config key"), "The warning goes to stderr: {}", stderr);
+    println!("test doctor_json_survives_logged_warnings ... ok");
+}